@@ -9,7 +9,7 @@ extern crate rayon;
 
 use chashmap::CHashMap;
 use criterion::Criterion;
-use poirot::ConcurrentHashMap;
+use poirot::{ConcurrentHashMap, ConcurrentIntMap};
 use rand::{thread_rng, Rng};
 use rayon::prelude::*;
 
@@ -33,6 +33,17 @@ fn poirot_single_thread_map_insert(c: &mut Criterion) {
     });
 }
 
+fn poirot_single_thread_int_map_insert(c: &mut Criterion) {
+    c.bench_function("poirot_single_thread_int_map_insert", |b| {
+        b.iter(|| {
+            let poirot_map = ConcurrentIntMap::new();
+            for x in RANDOM_VEC.iter().cloned() {
+                poirot_map.insert(x, x);
+            }
+        })
+    });
+}
+
 fn poirot_rayon_map_insert(c: &mut Criterion) {
     c.bench_function("poirot_rayon_map_insert", |b| {
         b.iter(|| {
@@ -102,6 +113,7 @@ fn chashmap_rayon_map_mutate(c: &mut Criterion) {
 criterion_group!(
     poirot_map,
     poirot_single_thread_map_insert,
+    poirot_single_thread_int_map_insert,
     poirot_rayon_map_insert,
     poirot_rayon_map_mutate
 );
@@ -0,0 +1,17 @@
+#!/bin/sh
+# Builds and tests the crate with no features, each feature on its own, and all
+# features together. Every optional feature must be additive.
+set -ex
+
+FEATURES="deadlock-detection deflate entry-metadata guard-timing lock-stats numa proptest quickcheck rand rayon"
+
+cargo clippy --all-targets --no-default-features -- -D warnings
+cargo test --no-default-features
+
+for feature in $FEATURES; do
+    cargo clippy --all-targets --no-default-features --features "$feature" -- -D warnings
+    cargo test --no-default-features --features "$feature"
+done
+
+cargo clippy --all-targets --all-features -- -D warnings
+cargo test --all-features
@@ -0,0 +1,213 @@
+// Runs a mixed read/write workload against a map and reports per-segment
+// throughput, time spent waiting for segment locks, and operation latency. Lock
+// waits come from the map's lock-stats counters, so they are reported separately
+// from the latency of the operations that include them.
+//
+// cargo run --release --features lock-stats --example contention_profile -- \
+//     [threads] [read_percent] [concurrency_level] [keys] [ops_per_thread] [random|fixed]
+
+extern crate poirot;
+extern crate rand;
+
+use poirot::{ConcurrentHashMap, SegmentLockStats};
+use rand::{thread_rng, Rng};
+
+use std::collections::hash_map::{DefaultHasher, RandomState};
+use std::env;
+use std::hash::{BuildHasher, BuildHasherDefault};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const LATENCY_BUCKETS_NANOS: [u64; 8] = [
+    250,
+    1_000,
+    4_000,
+    16_000,
+    64_000,
+    256_000,
+    1_000_000,
+    u64::MAX,
+];
+
+struct Config {
+    threads: usize,
+    read_percent: u32,
+    concurrency_level: usize,
+    keys: u64,
+    ops_per_thread: usize,
+}
+
+struct ThreadReport {
+    ops_per_segment: Vec<u64>,
+    latency_buckets: [u64; 8],
+    total_latency: Duration,
+}
+
+fn arg<T: std::str::FromStr>(args: &[String], index: usize, default: T) -> T {
+    args.get(index)
+        .map(|s| s.parse().unwrap_or_else(|_| panic!("bad argument: {}", s)))
+        .unwrap_or(default)
+}
+
+struct Report {
+    threads: Vec<ThreadReport>,
+    // Only the workload's acquisitions, without those of the initial inserts.
+    locks: Vec<SegmentLockStats>,
+    elapsed: Duration,
+}
+
+fn run<B>(config: &Config, hasher: B) -> Report
+where
+    B: BuildHasher + Default + Send + Sync + 'static,
+{
+    let map = Arc::new(ConcurrentHashMap::with_options(
+        config.keys as usize,
+        hasher,
+        config.concurrency_level,
+    ));
+    for k in 0..config.keys {
+        map.insert(k, k);
+    }
+    let setup_locks = map.lock_stats();
+
+    let started = Instant::now();
+    let handles: Vec<_> = (0..config.threads)
+        .map(|_| {
+            let map = map.clone();
+            let (read_percent, keys, ops) =
+                (config.read_percent, config.keys, config.ops_per_thread);
+            thread::spawn(move || {
+                let mut rng = thread_rng();
+                let mut report = ThreadReport {
+                    ops_per_segment: vec![0; map.segment_count()],
+                    latency_buckets: [0; 8],
+                    total_latency: Duration::from_secs(0),
+                };
+                for _ in 0..ops {
+                    let key = rng.gen_range(0, keys);
+                    let read = rng.gen_range(0, 100) < read_percent;
+                    let op_started = Instant::now();
+                    if read {
+                        map.get(&key).map(|v| *v);
+                    } else {
+                        map.insert(key, key);
+                    }
+                    let latency = op_started.elapsed();
+                    report.total_latency += latency;
+                    let nanos = latency.as_nanos() as u64;
+                    let bucket = LATENCY_BUCKETS_NANOS
+                        .iter()
+                        .position(|&bound| nanos < bound)
+                        .unwrap();
+                    report.latency_buckets[bucket] += 1;
+                    report.ops_per_segment[map.segment_index(&key)] += 1;
+                }
+                report
+            })
+        })
+        .collect();
+    let threads = handles.into_iter().map(|h| h.join().unwrap()).collect();
+    let elapsed = started.elapsed();
+    let locks = map
+        .lock_stats()
+        .into_iter()
+        .zip(setup_locks)
+        .map(|(after, before)| SegmentLockStats {
+            acquisitions: after.acquisitions - before.acquisitions,
+            contended: after.contended - before.contended,
+            contended_reads: after.contended_reads - before.contended_reads,
+            total_wait_time: after.total_wait_time - before.total_wait_time,
+            total_hold_time: after.total_hold_time - before.total_hold_time,
+            hold_time_histogram: Vec::new(),
+        })
+        .collect();
+    Report {
+        threads,
+        locks,
+        elapsed,
+    }
+}
+
+fn print_report(report: &Report) {
+    let reports = &report.threads;
+    let seconds = report.elapsed.as_secs_f64();
+    let segments = reports[0].ops_per_segment.len();
+    let total: u64 = reports.iter().flat_map(|r| r.ops_per_segment.iter()).sum();
+    println!(
+        "{} ops in {:.3}s ({:.0} ops/s)",
+        total,
+        seconds,
+        total as f64 / seconds
+    );
+
+    println!("\nper-segment throughput:");
+    for i in 0..segments {
+        let ops: u64 = reports.iter().map(|r| r.ops_per_segment[i]).sum();
+        println!(
+            "  segment {:>3}: {:>12.0} ops/s ({:>5.1}%)",
+            i,
+            ops as f64 / seconds,
+            ops as f64 * 100.0 / total as f64
+        );
+    }
+
+    let wait: Duration = report.locks.iter().map(|l| l.total_wait_time).sum();
+    let latency: Duration = reports.iter().map(|r| r.total_latency).sum();
+    println!(
+        "\nlock wait: {:?} of {:?} total operation time ({:.1}%)",
+        wait,
+        latency,
+        wait.as_secs_f64() * 100.0 / latency.as_secs_f64()
+    );
+    for (i, locks) in report.locks.iter().enumerate() {
+        println!(
+            "  segment {:>3}: {:>8} contended writes, {:>8} contended reads, {:?} waiting",
+            i, locks.contended, locks.contended_reads, locks.total_wait_time
+        );
+    }
+
+    println!("\noperation latency, including lock wait:");
+    for (i, &bound) in LATENCY_BUCKETS_NANOS.iter().enumerate() {
+        let count: u64 = reports.iter().map(|r| r.latency_buckets[i]).sum();
+        let label = if bound == u64::MAX {
+            "   slower".to_string()
+        } else {
+            format!("< {:>7}", format!("{:?}", Duration::from_nanos(bound)))
+        };
+        println!(
+            "  {}: {:>10} ({:>5.1}%)",
+            label,
+            count,
+            count as f64 * 100.0 / total as f64
+        );
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let config = Config {
+        threads: arg(&args, 0, 4),
+        read_percent: arg(&args, 1, 90),
+        concurrency_level: arg(&args, 2, 16),
+        keys: arg(&args, 3, 10_000),
+        ops_per_thread: arg(&args, 4, 1_000_000),
+    };
+    let hasher: String = arg(&args, 5, "random".to_string());
+
+    println!(
+        "{} threads, {}% reads, {} segments, {} keys, {} ops/thread, {} hasher\n",
+        config.threads,
+        config.read_percent,
+        config.concurrency_level.next_power_of_two(),
+        config.keys,
+        config.ops_per_thread,
+        hasher
+    );
+    let report = match hasher.as_str() {
+        "random" => run(&config, RandomState::new()),
+        "fixed" => run(&config, BuildHasherDefault::<DefaultHasher>::default()),
+        other => panic!("unknown hasher {:?}, expected random or fixed", other),
+    };
+    print_report(&report);
+}
@@ -0,0 +1,230 @@
+use std::borrow::Borrow;
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::collections::HashSet;
+use std::hash::{BuildHasher, Hash};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::{ConcurrentHashMap, ReadGuard};
+
+static NEXT_THREAD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static THREAD_SLOT: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+// Threads are numbered in the order they first route a write.
+fn thread_slot() -> usize {
+    THREAD_SLOT.with(|slot| {
+        slot.get().unwrap_or_else(|| {
+            let assigned = NEXT_THREAD.fetch_add(1, Ordering::Relaxed);
+            slot.set(Some(assigned));
+            assigned
+        })
+    })
+}
+
+enum Routing {
+    ThreadGroups,
+    #[cfg(feature = "numa")]
+    NumaNodes(Vec<Option<usize>>),
+}
+
+/// A set of independent maps, one per group of threads, for workloads where
+/// producers write and a consumer later drains each shard as a whole.
+///
+/// `insert_local` writes to the calling thread's own shard, so producers in
+/// different groups never share a segment lock. This makes it a multimap: a key
+/// written from several groups has an entry, with its own value, in each of
+/// their shards. `get_local` reads the caller's entry and `get_all` every
+/// shard's; `len` counts every entry, and `key_count` distinct keys.
+///
+/// With the numa feature, `per_numa_node` picks the shard from the CPU the
+/// calling thread is running on. That is the only NUMA awareness: shard memory
+/// is not placed on any particular node, and a thread that migrates starts
+/// writing to another shard.
+pub struct AffinityMap<K, V, B = RandomState> {
+    shards: Vec<ConcurrentHashMap<K, V, B>>,
+    routing: Routing,
+}
+
+impl<K: Eq + Hash, V, B: BuildHasher + Default> AffinityMap<K, V, B> {
+    /// Spreads threads round-robin over `groups` shards.
+    pub fn new(groups: usize) -> Self {
+        assert!(groups > 0, "AffinityMap needs at least one group");
+        AffinityMap {
+            shards: (0..groups).map(|_| ConcurrentHashMap::default()).collect(),
+            routing: Routing::ThreadGroups,
+        }
+    }
+
+    /// One shard per NUMA node, each written by the threads currently running
+    /// on that node. This only routes writes; where shard memory is placed is
+    /// left to the allocator and the kernel. Falls back to a single shard if the
+    /// topology cannot be read from `/sys`.
+    #[cfg(feature = "numa")]
+    pub fn per_numa_node() -> Self {
+        let (nodes, cpu_nodes) = numa::topology();
+        AffinityMap {
+            shards: (0..nodes).map(|_| ConcurrentHashMap::default()).collect(),
+            routing: Routing::NumaNodes(cpu_nodes),
+        }
+    }
+
+    /// The shard the calling thread writes to.
+    pub fn local_shard(&self) -> usize {
+        match self.routing {
+            Routing::ThreadGroups => thread_slot() % self.shards.len(),
+            #[cfg(feature = "numa")]
+            Routing::NumaNodes(ref cpu_nodes) => numa::current_cpu()
+                .and_then(|cpu| cpu_nodes.get(cpu).cloned())
+                .and_then(|node| node)
+                .unwrap_or_else(|| thread_slot() % self.shards.len()),
+        }
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn shard(&self, index: usize) -> &ConcurrentHashMap<K, V, B> {
+        &self.shards[index]
+    }
+
+    pub fn insert_local(&self, key: K, value: V) -> Option<V> {
+        self.shards[self.local_shard()].insert(key, value)
+    }
+
+    pub fn insert_or_update_local<F, G>(&self, key: K, insert: F, update: G)
+    where
+        F: FnOnce() -> V,
+        G: FnOnce(&mut V),
+    {
+        self.shards[self.local_shard()].insert_or_update(key, insert, update)
+    }
+
+    /// The caller's own entry for `key`, from its local shard.
+    pub fn get_local<Q>(&self, key: &Q) -> Option<ReadGuard<'_, K, V, B>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.shards[self.local_shard()].get(key)
+    }
+
+    /// Every shard's entry for `key`, with the shard's index, in shard order.
+    /// Each shard is read under its own lock, so this is not an atomic view.
+    pub fn get_all<Q>(&self, key: &Q) -> Vec<(usize, ReadGuard<'_, K, V, B>)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.shards
+            .iter()
+            .enumerate()
+            .filter_map(|(i, shard)| shard.get(key).map(|guard| (i, guard)))
+            .collect()
+    }
+
+    /// Empties shard `index` and returns its contents; see `ConcurrentHashMap::take`.
+    pub fn drain_shard(&self, index: usize) -> ConcurrentHashMap<K, V, B>
+    where
+        B: Clone,
+    {
+        self.shards[index].take()
+    }
+
+    /// The number of entries in all shards; a key held by several shards is
+    /// counted once for each.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.len()).sum()
+    }
+
+    /// The number of distinct keys across all shards.
+    pub fn key_count(&self) -> usize
+    where
+        K: Clone,
+    {
+        let mut keys = HashSet::new();
+        for shard in &self.shards {
+            for i in 0..shard.segment_count() {
+                keys.extend(shard.read_segment(i).keys().cloned());
+            }
+        }
+        keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| shard.is_empty())
+    }
+}
+
+#[cfg(feature = "numa")]
+mod numa {
+    use std::fs;
+
+    // The number of nodes, and for each CPU the index of its node among them.
+    pub(super) fn topology() -> (usize, Vec<Option<usize>>) {
+        let mut nodes: Vec<(usize, Vec<usize>)> = fs::read_dir("/sys/devices/system/node")
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let id = entry
+                    .file_name()
+                    .to_str()?
+                    .strip_prefix("node")?
+                    .parse()
+                    .ok()?;
+                let cpus = fs::read_to_string(entry.path().join("cpulist")).ok()?;
+                Some((id, parse_cpu_list(&cpus)))
+            })
+            .collect();
+        if nodes.is_empty() {
+            return (1, Vec::new());
+        }
+        nodes.sort();
+        let mut cpu_nodes = Vec::new();
+        for (index, (_, cpus)) in nodes.iter().enumerate() {
+            for &cpu in cpus {
+                if cpu_nodes.len() <= cpu {
+                    cpu_nodes.resize(cpu + 1, None);
+                }
+                cpu_nodes[cpu] = Some(index);
+            }
+        }
+        (nodes.len(), cpu_nodes)
+    }
+
+    // Parses the kernel's list format, e.g. "0-3,8-11".
+    fn parse_cpu_list(list: &str) -> Vec<usize> {
+        list.trim()
+            .split(',')
+            .filter(|range| !range.is_empty())
+            .filter_map(|range| {
+                let mut bounds = range.splitn(2, '-').map(|n| n.parse::<usize>().ok());
+                let start = bounds.next()??;
+                let end = bounds.next().unwrap_or(Some(start))?;
+                Some(start..=end)
+            })
+            .flatten()
+            .collect()
+    }
+
+    #[cfg(target_os = "linux")]
+    pub(super) fn current_cpu() -> Option<usize> {
+        // SAFETY: `sched_getcpu` takes no arguments, touches no caller memory
+        // and reports failure as -1, which is handled below.
+        let cpu = unsafe { ::libc::sched_getcpu() };
+        if cpu < 0 {
+            None
+        } else {
+            Some(cpu as usize)
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub(super) fn current_cpu() -> Option<usize> {
+        None
+    }
+}
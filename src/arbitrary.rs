@@ -0,0 +1,63 @@
+use quickcheck::{Arbitrary, Gen};
+
+use std::hash::{BuildHasher, Hash};
+
+use super::{ConcurrentHashMap, ConcurrentHashSet};
+
+// Between 1 and 32 segments.
+fn concurrency_level<G: Gen>(g: &mut G) -> usize {
+    1 << (u8::arbitrary(g) % 6)
+}
+
+fn build<K, V, B, I>(concurrency_level: usize, entries: I) -> ConcurrentHashMap<K, V, B>
+where
+    K: Eq + Hash,
+    B: BuildHasher + Default,
+    I: IntoIterator<Item = (K, V)>,
+{
+    let map = ConcurrentHashMap::with_options(0, B::default(), concurrency_level);
+    for (k, v) in entries {
+        map.insert(k, v);
+    }
+    map
+}
+
+/// Generates maps with arbitrary entries and segment counts. Shrinking keeps
+/// the segment count and shrinks the entries.
+impl<K, V, B> Arbitrary for ConcurrentHashMap<K, V, B>
+where
+    K: Arbitrary + Eq + Hash + Sync,
+    V: Arbitrary + Sync,
+    B: BuildHasher + Default + Clone + Send + Sync + 'static,
+{
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        let concurrency_level = concurrency_level(g);
+        build(concurrency_level, Vec::<(K, V)>::arbitrary(g))
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let concurrency_level = self.segment_count();
+        let entries: Vec<(K, V)> = self.iter().collect();
+        Box::new(
+            entries
+                .shrink()
+                .map(move |entries| build(concurrency_level, entries)),
+        )
+    }
+}
+
+impl<K, B> Arbitrary for ConcurrentHashSet<K, B>
+where
+    K: Arbitrary + Eq + Hash + Sync,
+    B: BuildHasher + Default + Clone + Send + Sync + 'static,
+{
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        ConcurrentHashSet {
+            table: ConcurrentHashMap::arbitrary(g),
+        }
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        Box::new(self.table.shrink().map(|table| ConcurrentHashSet { table }))
+    }
+}
@@ -0,0 +1,102 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
+
+use super::{ConcurrentHashMap, ReadGuard};
+
+/// Converts values to and from the bytes a `CodecMap` stores.
+///
+/// `decode` is only ever given bytes that `encode` produced.
+pub trait ValueCodec<V>: Send + Sync {
+    fn encode(&self, value: &V) -> Vec<u8>;
+    fn decode(&self, bytes: &[u8]) -> V;
+}
+
+/// A map that keeps its values encoded, trading a decode on every read for a
+/// smaller resident size. Values are decoded and encoded outside the segment
+/// locks, so reads hand back owned values rather than guards.
+pub struct CodecMap<K, V, C, B = RandomState> {
+    map: ConcurrentHashMap<K, Box<[u8]>, B>,
+    codec: C,
+    marker: PhantomData<fn(V) -> V>,
+}
+
+impl<K: Eq + Hash, V, C: ValueCodec<V>> CodecMap<K, V, C, RandomState> {
+    pub fn new(codec: C) -> Self {
+        CodecMap::with_map(ConcurrentHashMap::new(), codec)
+    }
+}
+
+impl<K, V, C, B> CodecMap<K, V, C, B>
+where
+    K: Eq + Hash,
+    C: ValueCodec<V>,
+    B: BuildHasher + Default,
+{
+    /// Wraps `map`, whose entries, if any, must have been encoded by `codec`.
+    pub fn with_map(map: ConcurrentHashMap<K, Box<[u8]>, B>, codec: C) -> Self {
+        CodecMap {
+            map,
+            codec,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        let encoded = self.codec.encode(&value).into_boxed_slice();
+        self.map
+            .insert(key, encoded)
+            .map(|previous| self.codec.decode(&previous))
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        // Copy the bytes out so the segment lock is not held while decoding.
+        let encoded = self.map.get(key)?.clone();
+        Some(self.codec.decode(&encoded))
+    }
+
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.map
+            .remove(key)
+            .map(|encoded| self.codec.decode(&encoded))
+    }
+
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.map.contains(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn codec(&self) -> &C {
+        &self.codec
+    }
+
+    /// The encoded bytes stored for `key`. They can only be read, so every
+    /// stored value is one that `encode` produced.
+    pub fn encoded<Q>(&self, key: &Q) -> Option<ReadGuard<'_, K, Box<[u8]>, B>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.map.get(key)
+    }
+}
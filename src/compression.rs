@@ -0,0 +1,69 @@
+use miniz_oxide::deflate::compress_to_vec;
+use miniz_oxide::inflate::decompress_to_vec;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::ValueCodec;
+
+/// Wraps another codec and deflates its output. Since a `CodecMap` owns its
+/// codec, the byte counters here describe the values written to that map.
+pub struct DeflateCodec<C> {
+    inner: C,
+    level: u8,
+    uncompressed_bytes: AtomicU64,
+    compressed_bytes: AtomicU64,
+}
+
+/// Totals over every value encoded so far, including ones since overwritten
+/// or removed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompressionStats {
+    pub uncompressed_bytes: u64,
+    pub compressed_bytes: u64,
+}
+
+impl CompressionStats {
+    /// Compressed size as a fraction of the uncompressed size.
+    pub fn ratio(&self) -> f64 {
+        if self.uncompressed_bytes == 0 {
+            return 1.0;
+        }
+        self.compressed_bytes as f64 / self.uncompressed_bytes as f64
+    }
+}
+
+impl<C> DeflateCodec<C> {
+    /// `level` runs from 0 (store only) to 10 (smallest output).
+    pub fn new(inner: C, level: u8) -> Self {
+        DeflateCodec {
+            inner,
+            level,
+            uncompressed_bytes: AtomicU64::new(0),
+            compressed_bytes: AtomicU64::new(0),
+        }
+    }
+
+    pub fn stats(&self) -> CompressionStats {
+        CompressionStats {
+            uncompressed_bytes: self.uncompressed_bytes.load(Ordering::Relaxed),
+            compressed_bytes: self.compressed_bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl<V, C: ValueCodec<V>> ValueCodec<V> for DeflateCodec<C> {
+    fn encode(&self, value: &V) -> Vec<u8> {
+        let raw = self.inner.encode(value);
+        let compressed = compress_to_vec(&raw, self.level);
+        self.uncompressed_bytes
+            .fetch_add(raw.len() as u64, Ordering::Relaxed);
+        self.compressed_bytes
+            .fetch_add(compressed.len() as u64, Ordering::Relaxed);
+        compressed
+    }
+
+    fn decode(&self, bytes: &[u8]) -> V {
+        let raw = decompress_to_vec(bytes).expect("stored value is not valid deflate data");
+        self.inner.decode(&raw)
+    }
+}
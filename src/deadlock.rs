@@ -0,0 +1,73 @@
+use parking_lot::deadlock;
+use thread_id;
+
+use std::sync::Mutex;
+
+// (thread id, map address, segment index) for every thread blocked on a segment lock.
+static WAITING: Mutex<Vec<(usize, usize, usize)>> = Mutex::new(Vec::new());
+
+/// Identifies a segment lock: the address of the owning `ConcurrentHashMap` and
+/// the segment's index within it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SegmentId {
+    pub map: usize,
+    pub segment: usize,
+}
+
+#[derive(Debug)]
+pub struct DeadlockedThread {
+    pub thread_id: usize,
+    /// The poirot segment this thread is blocked on, if the blocking lock is one.
+    pub waiting_on: Option<SegmentId>,
+    pub backtrace: String,
+}
+
+/// Runs parking_lot's deadlock detector and returns each cycle found since the
+/// last call, annotated with the segments the threads are waiting for.
+pub fn check_deadlocks() -> Vec<Vec<DeadlockedThread>> {
+    let cycles = deadlock::check_deadlock();
+    if cycles.is_empty() {
+        return Vec::new();
+    }
+    let waiting = WAITING.lock().unwrap_or_else(|e| e.into_inner());
+    cycles
+        .iter()
+        .map(|threads| {
+            threads
+                .iter()
+                .map(|thread| DeadlockedThread {
+                    thread_id: thread.thread_id(),
+                    waiting_on: waiting
+                        .iter()
+                        .find(|&&(id, _, _)| id == thread.thread_id())
+                        .map(|&(_, map, segment)| SegmentId { map, segment }),
+                    backtrace: format!("{:?}", thread.backtrace()),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+pub(crate) struct Waiting {
+    thread_id: usize,
+}
+
+impl Waiting {
+    pub(crate) fn new(map: usize, segment: usize) -> Self {
+        let thread_id = thread_id::get();
+        WAITING
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push((thread_id, map, segment));
+        Waiting { thread_id }
+    }
+}
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        let mut waiting = WAITING.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(i) = waiting.iter().rposition(|&(id, _, _)| id == self.thread_id) {
+            waiting.swap_remove(i);
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::borrow::Borrow;
+use std::hash::{BuildHasher, Hash};
+
+use super::{
+    ConcurrentHashMap, Error, InsertError, PendingMutation, SegmentWriteGuard, WriteGuard,
+};
+
+impl<K, V, B> ConcurrentHashMap<K, V, B>
+where
+    K: Eq + Hash,
+    B: BuildHasher + Default,
+{
+    /// Locks `key`'s segment and returns its entry, which holds the lock until
+    /// it is dropped or turned into a guard. The owned key is only built, with
+    /// `to_owned`, when a vacant entry is filled.
+    pub fn entry_ref<'a, 'q, Q>(&'a self, key: &'q Q) -> EntryRef<'a, 'q, K, V, B, Q>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash + ToOwned<Owned = K>,
+    {
+        let locked = Locked {
+            map: self,
+            lock: self.write_segment(self.get_segment(self.hash(key))),
+            key,
+        };
+        if locked.lock.contains_key(key) {
+            EntryRef::Occupied(OccupiedEntryRef { locked })
+        } else {
+            EntryRef::Vacant(VacantEntryRef { locked })
+        }
+    }
+}
+
+struct Locked<'a, 'q, K: 'a, V: 'a, B: 'a, Q: ?Sized + 'q> {
+    map: &'a ConcurrentHashMap<K, V, B>,
+    lock: SegmentWriteGuard<'a, K, V, B>,
+    key: &'q Q,
+}
+
+pub enum EntryRef<'a, 'q, K: 'a, V: 'a, B: 'a, Q: ?Sized + 'q> {
+    Occupied(OccupiedEntryRef<'a, 'q, K, V, B, Q>),
+    Vacant(VacantEntryRef<'a, 'q, K, V, B, Q>),
+}
+
+impl<'a, 'q, K, V, B, Q> EntryRef<'a, 'q, K, V, B, Q>
+where
+    K: Eq + Hash + Borrow<Q>,
+    B: BuildHasher + Default,
+    Q: ?Sized + Eq + Hash + ToOwned<Owned = K>,
+{
+    pub fn key(&self) -> &'q Q {
+        match *self {
+            EntryRef::Occupied(ref entry) => entry.locked.key,
+            EntryRef::Vacant(ref entry) => entry.locked.key,
+        }
+    }
+
+    /// Returns a guard on the value, inserting `value` first if the entry is
+    /// vacant. Fails with `Error::Closed` if the entry is vacant and the map
+    /// has been closed.
+    pub fn or_insert(self, value: V) -> Result<WriteGuard<'a, K, V, B>, Error> {
+        self.or_insert_with(|| value)
+    }
+
+    /// Like `or_insert`, but only calls `f` if the entry is vacant and the map
+    /// is open.
+    pub fn or_insert_with<F>(self, f: F) -> Result<WriteGuard<'a, K, V, B>, Error>
+    where
+        F: FnOnce() -> V,
+    {
+        match self {
+            EntryRef::Occupied(entry) => Ok(entry.into_guard()),
+            EntryRef::Vacant(ref entry) if entry.locked.map.is_closed() => Err(Error::Closed),
+            EntryRef::Vacant(entry) => entry.insert(f()).map_err(|error| error.error),
+        }
+    }
+
+    pub fn or_default(self) -> Result<WriteGuard<'a, K, V, B>, Error>
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    /// Calls `f` on the value if the entry is occupied. The change is not
+    /// reported to the observer.
+    pub fn and_modify<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut V),
+    {
+        if let EntryRef::Occupied(ref mut entry) = self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+pub struct OccupiedEntryRef<'a, 'q, K: 'a, V: 'a, B: 'a, Q: ?Sized + 'q> {
+    locked: Locked<'a, 'q, K, V, B, Q>,
+}
+
+impl<'a, 'q, K, V, B, Q> OccupiedEntryRef<'a, 'q, K, V, B, Q>
+where
+    K: Eq + Hash + Borrow<Q>,
+    B: BuildHasher + Default,
+    Q: ?Sized + Eq + Hash,
+{
+    pub fn get(&self) -> &V {
+        &self.locked.lock[self.locked.key]
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        let key = self.locked.key;
+        self.locked.lock.get_mut(key).unwrap()
+    }
+
+    /// Turns the entry into a guard on its value, keeping the segment locked.
+    #[cfg_attr(not(feature = "entry-metadata"), allow(unused_variables))]
+    pub fn into_guard(self) -> WriteGuard<'a, K, V, B> {
+        let Locked { map, lock, key } = self.locked;
+        #[cfg(feature = "entry-metadata")]
+        let metadata = lock.accessed(key, map.clock.now()).unwrap();
+        let guard = lock.into_write_guard(|segment| segment.get_mut(key).unwrap());
+        #[cfg(feature = "entry-metadata")]
+        let guard = guard.with_metadata(metadata);
+        guard
+    }
+
+    /// Removes the entry and returns its value, or `None` if the key is pinned.
+    pub fn remove(self) -> Option<V> {
+        let Locked { map, mut lock, key } = self.locked;
+        if map.pins.any() && lock.is_pinned(key) {
+            return None;
+        }
+        let (k, v) = lock.remove_entry(key).unwrap();
+        drop(lock);
+        if let Some(ref hook) = map.observer {
+            hook.observer.on_remove(&k, &v);
+        }
+        Some(v)
+    }
+}
+
+pub struct VacantEntryRef<'a, 'q, K: 'a, V: 'a, B: 'a, Q: ?Sized + 'q> {
+    locked: Locked<'a, 'q, K, V, B, Q>,
+}
+
+impl<'a, 'q, K, V, B, Q> VacantEntryRef<'a, 'q, K, V, B, Q>
+where
+    K: Eq + Hash + Borrow<Q>,
+    B: BuildHasher + Default,
+    Q: ?Sized + Eq + Hash + ToOwned<Owned = K>,
+{
+    /// Inserts `value` under an owned copy of the key and returns a guard on it.
+    /// The insert is reported to the observer when the guard is dropped. Fails
+    /// with `Error::Closed`, handing the entry back, if the map has been closed.
+    pub fn insert(self, value: V) -> Result<WriteGuard<'a, K, V, B>, InsertError<K, V>> {
+        let Locked { map, lock, key } = self.locked;
+        let key = key.to_owned();
+        if map.is_closed() {
+            return Err(InsertError::new(Error::Closed, key, value));
+        }
+        let slot = map.slot(value);
+        let pending = map.observer.as_ref().map(|hook| PendingMutation {
+            hook,
+            key: (hook.clone_key)(&key),
+            created: true,
+        });
+        #[cfg(feature = "entry-metadata")]
+        let metadata = slot.metadata().unwrap();
+        let guard = lock
+            .into_write_guard(|segment| &mut segment.table_mut().entry(key).or_insert(slot).value)
+            .with_pending(pending);
+        #[cfg(feature = "entry-metadata")]
+        let guard = guard.with_metadata(metadata);
+        Ok(guard)
+    }
+}
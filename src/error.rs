@@ -0,0 +1,97 @@
+use std::error;
+use std::fmt::{self, Display, Formatter};
+
+/// Why a fallible map operation did not complete.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The segment lock was held and the operation was asked not to wait.
+    WouldBlock,
+    /// The segment lock could not be acquired within the given timeout.
+    Timeout,
+    /// The key's segment is at its share of the map's capacity limit.
+    CapacityExceeded,
+    /// The map has been closed.
+    Closed,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let message = match *self {
+            Error::WouldBlock => "segment lock is held",
+            Error::Timeout => "timed out waiting for segment lock",
+            Error::CapacityExceeded => "segment is at capacity",
+            Error::Closed => "map is closed",
+        };
+        f.write_str(message)
+    }
+}
+
+impl error::Error for Error {}
+
+/// Why `get_or_try_insert_with` did not return a guard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GetOrInsertError<E> {
+    /// The key was absent and the map has been closed; the value was not built.
+    Closed,
+    /// Building the value failed with this error.
+    Failed(E),
+}
+
+impl<E: Display> Display for GetOrInsertError<E> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            GetOrInsertError::Closed => Display::fmt(&Error::Closed, f),
+            GetOrInsertError::Failed(ref error) => {
+                write!(f, "building the value failed: {}", error)
+            }
+        }
+    }
+}
+
+impl<E: error::Error> error::Error for GetOrInsertError<E> {}
+
+/// A rejected insert, handing the entry back along with the reason.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InsertError<K, V> {
+    pub error: Error,
+    pub key: K,
+    pub value: V,
+}
+
+impl<K, V> InsertError<K, V> {
+    pub(crate) fn new(error: Error, key: K, value: V) -> Self {
+        InsertError { error, key, value }
+    }
+
+    pub fn into_entry(self) -> (K, V) {
+        (self.key, self.value)
+    }
+}
+
+impl<K, V> Display for InsertError<K, V> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "insert rejected: {}", self.error)
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug> error::Error for InsertError<K, V> {}
+
+/// Why `apply` stopped: an operation's sequence number did not follow the one
+/// before it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SequenceError {
+    pub expected: u64,
+    pub found: u64,
+}
+
+impl Display for SequenceError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "operation {} is out of sequence, expected {}",
+            self.found, self.expected
+        )
+    }
+}
+
+impl error::Error for SequenceError {}
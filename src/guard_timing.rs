@@ -0,0 +1,49 @@
+use log::warn;
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static THRESHOLD_NANOS: AtomicU64 = AtomicU64::new(100_000_000);
+static PANIC_ON_LONG_HOLD: AtomicBool = AtomicBool::new(false);
+
+/// Sets how long a `ReadGuard` or `WriteGuard` may be held before a warning is
+/// logged through the `log` crate.
+pub fn set_guard_hold_threshold(threshold: Duration) {
+    THRESHOLD_NANOS.store(threshold.as_nanos() as u64, Ordering::Relaxed);
+}
+
+/// Panic instead of logging a warning when a guard is held too long. Meant for tests.
+pub fn set_panic_on_long_hold(panic: bool) {
+    PANIC_ON_LONG_HOLD.store(panic, Ordering::Relaxed);
+}
+
+pub(crate) struct HoldTimer {
+    acquired: Instant,
+}
+
+impl HoldTimer {
+    pub(crate) fn start() -> Self {
+        HoldTimer {
+            acquired: Instant::now(),
+        }
+    }
+
+    pub(crate) fn finish(&self, guard: &str) {
+        let held = self.acquired.elapsed();
+        let threshold = Duration::from_nanos(THRESHOLD_NANOS.load(Ordering::Relaxed));
+        if held <= threshold {
+            return;
+        }
+        if PANIC_ON_LONG_HOLD.load(Ordering::Relaxed) && !thread::panicking() {
+            panic!(
+                "poirot: {} held for {:?}, longer than {:?}",
+                guard, held, threshold
+            );
+        }
+        warn!(
+            "poirot: {} held for {:?}, longer than {:?}",
+            guard, held, threshold
+        );
+    }
+}
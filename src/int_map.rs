@@ -0,0 +1,307 @@
+use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use std::mem;
+use std::ops::{Deref, DerefMut};
+
+use super::{DEFAULT_INITIAL_CAPACITY, DEFAULT_SEGMENT_COUNT};
+
+/// Integer types usable as `ConcurrentIntMap` keys.
+pub trait IntKey: Copy + Eq {
+    fn to_u64(self) -> u64;
+}
+
+impl IntKey for u32 {
+    #[inline]
+    fn to_u64(self) -> u64 {
+        u64::from(self)
+    }
+}
+
+impl IntKey for u64 {
+    #[inline]
+    fn to_u64(self) -> u64 {
+        self
+    }
+}
+
+// The splitmix64 finalizer: cheap, and spreads sequential or strided keys over
+// both the high bits (which pick the segment) and the low bits (which pick the
+// slot).
+#[inline]
+fn mix(key: u64) -> u64 {
+    let mut x = key;
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// A map specialised to integer keys. Keys are mixed with a fixed function
+/// instead of a `BuildHasher`, and each segment is an open-addressing table, so
+/// lookups touch no per-entry allocations. It has none of `ConcurrentHashMap`'s
+/// observers, pins or metadata.
+pub struct ConcurrentIntMap<K, V> {
+    segments: Vec<RwLock<Table<K, V>>>,
+    shift: u32,
+}
+
+impl<K: IntKey, V> ConcurrentIntMap<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_options(capacity: usize, concurrency_level: usize) -> Self {
+        let concurrency_level = concurrency_level.next_power_of_two();
+        let per_segment_capacity = capacity / concurrency_level;
+        ConcurrentIntMap {
+            segments: (0..concurrency_level)
+                .map(|_| RwLock::new(Table::with_capacity(per_segment_capacity)))
+                .collect(),
+            shift: 64 - concurrency_level.trailing_zeros(),
+        }
+    }
+
+    #[inline]
+    fn segment(&self, hash: u64) -> usize {
+        // A shift of 64 means a single segment.
+        hash.checked_shr(self.shift).unwrap_or(0) as usize
+    }
+
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        let hash = mix(key.to_u64());
+        self.segments[self.segment(hash)]
+            .write()
+            .insert(key, hash, value)
+    }
+
+    pub fn get(&self, key: K) -> Option<IntReadGuard<'_, V>> {
+        let hash = mix(key.to_u64());
+        let table = self.segments[self.segment(hash)].read();
+        let index = table.find(key, hash)?;
+        Some(IntReadGuard {
+            inner: RwLockReadGuard::map(table, |table| table.value(index)),
+        })
+    }
+
+    pub fn get_mut(&self, key: K) -> Option<IntWriteGuard<'_, V>> {
+        let hash = mix(key.to_u64());
+        let table = self.segments[self.segment(hash)].write();
+        let index = table.find(key, hash)?;
+        Some(IntWriteGuard {
+            inner: RwLockWriteGuard::map(table, |table| table.value_mut(index)),
+        })
+    }
+
+    pub fn insert_or_update<F, G>(&self, key: K, insert: F, update: G)
+    where
+        F: FnOnce() -> V,
+        G: FnOnce(&mut V),
+    {
+        let hash = mix(key.to_u64());
+        let mut table = self.segments[self.segment(hash)].write();
+        match table.find(key, hash) {
+            Some(index) => update(table.value_mut(index)),
+            None => {
+                table.insert(key, hash, insert());
+            }
+        }
+    }
+
+    pub fn remove(&self, key: K) -> Option<V> {
+        let hash = mix(key.to_u64());
+        self.segments[self.segment(hash)].write().remove(key, hash)
+    }
+
+    pub fn contains(&self, key: K) -> bool {
+        let hash = mix(key.to_u64());
+        self.segments[self.segment(hash)]
+            .read()
+            .find(key, hash)
+            .is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.segments.iter().map(|table| table.read().len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.iter().all(|table| table.read().len == 0)
+    }
+
+    /// Empties every segment, keeping its capacity.
+    pub fn clear(&self) {
+        for table in &self.segments {
+            table.write().clear();
+        }
+    }
+}
+
+impl<K: IntKey, V> Default for ConcurrentIntMap<K, V> {
+    fn default() -> Self {
+        ConcurrentIntMap::with_options(DEFAULT_INITIAL_CAPACITY, DEFAULT_SEGMENT_COUNT)
+    }
+}
+
+enum Slot<K, V> {
+    Empty,
+    Tombstone,
+    Full(K, V),
+}
+
+// Linear probing from the low bits of the key's hash. Removed entries leave a
+// tombstone unless the next slot is empty, so probe chains stay intact; the
+// table is rebuilt once live entries and tombstones fill three quarters of it,
+// which also guarantees every probe reaches an empty slot.
+struct Table<K, V> {
+    slots: Vec<Slot<K, V>>,
+    len: usize,
+    tombstones: usize,
+}
+
+impl<K: IntKey, V> Table<K, V> {
+    fn with_capacity(capacity: usize) -> Self {
+        let slots = (capacity * 4 / 3 + 1).next_power_of_two().max(8);
+        Table {
+            slots: Table::empty_slots(slots),
+            len: 0,
+            tombstones: 0,
+        }
+    }
+
+    fn empty_slots(count: usize) -> Vec<Slot<K, V>> {
+        (0..count).map(|_| Slot::Empty).collect()
+    }
+
+    #[inline]
+    fn mask(&self) -> usize {
+        self.slots.len() - 1
+    }
+
+    fn find(&self, key: K, hash: u64) -> Option<usize> {
+        let mut index = hash as usize & self.mask();
+        loop {
+            match self.slots[index] {
+                Slot::Empty => return None,
+                Slot::Full(k, _) if k == key => return Some(index),
+                _ => index = (index + 1) & self.mask(),
+            }
+        }
+    }
+
+    fn value(&self, index: usize) -> &V {
+        match self.slots[index] {
+            Slot::Full(_, ref value) => value,
+            _ => unreachable!("no entry at slot {}", index),
+        }
+    }
+
+    fn value_mut(&mut self, index: usize) -> &mut V {
+        match self.slots[index] {
+            Slot::Full(_, ref mut value) => value,
+            _ => unreachable!("no entry at slot {}", index),
+        }
+    }
+
+    fn insert(&mut self, key: K, hash: u64, value: V) -> Option<V> {
+        if (self.len + self.tombstones + 1) * 4 > self.slots.len() * 3 {
+            self.rebuild();
+        }
+        let mut index = hash as usize & self.mask();
+        let mut tombstone = None;
+        loop {
+            match self.slots[index] {
+                Slot::Empty => break,
+                Slot::Tombstone => {
+                    if tombstone.is_none() {
+                        tombstone = Some(index);
+                    }
+                }
+                Slot::Full(k, ref mut current) if k == key => {
+                    return Some(mem::replace(current, value));
+                }
+                Slot::Full(..) => {}
+            }
+            index = (index + 1) & self.mask();
+        }
+        if let Some(reused) = tombstone {
+            index = reused;
+            self.tombstones -= 1;
+        }
+        self.slots[index] = Slot::Full(key, value);
+        self.len += 1;
+        None
+    }
+
+    fn remove(&mut self, key: K, hash: u64) -> Option<V> {
+        let index = self.find(key, hash)?;
+        let next = (index + 1) & self.mask();
+        let vacated = match self.slots[next] {
+            Slot::Empty => Slot::Empty,
+            _ => {
+                self.tombstones += 1;
+                Slot::Tombstone
+            }
+        };
+        self.len -= 1;
+        match mem::replace(&mut self.slots[index], vacated) {
+            Slot::Full(_, value) => Some(value),
+            _ => unreachable!(),
+        }
+    }
+
+    // Doubles the table if live entries are at least half of it, and otherwise
+    // only clears out tombstones.
+    fn rebuild(&mut self) {
+        let slots = if (self.len + 1) * 2 > self.slots.len() {
+            self.slots.len() * 2
+        } else {
+            self.slots.len()
+        };
+        let old = mem::replace(&mut self.slots, Table::empty_slots(slots));
+        self.tombstones = 0;
+        for slot in old {
+            if let Slot::Full(key, value) = slot {
+                let mut index = mix(key.to_u64()) as usize & self.mask();
+                while let Slot::Full(..) = self.slots[index] {
+                    index = (index + 1) & self.mask();
+                }
+                self.slots[index] = Slot::Full(key, value);
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        for slot in &mut self.slots {
+            *slot = Slot::Empty;
+        }
+        self.len = 0;
+        self.tombstones = 0;
+    }
+}
+
+pub struct IntReadGuard<'a, V: 'a> {
+    inner: RwLockReadGuard<'a, V>,
+}
+
+impl<'a, V: 'a> Deref for IntReadGuard<'a, V> {
+    type Target = V;
+    fn deref(&self) -> &V {
+        &self.inner
+    }
+}
+
+pub struct IntWriteGuard<'a, V: 'a> {
+    inner: RwLockWriteGuard<'a, V>,
+}
+
+impl<'a, V: 'a> Deref for IntWriteGuard<'a, V> {
+    type Target = V;
+    fn deref(&self) -> &V {
+        &self.inner
+    }
+}
+
+impl<'a, V: 'a> DerefMut for IntWriteGuard<'a, V> {
+    fn deref_mut(&mut self) -> &mut V {
+        &mut self.inner
+    }
+}
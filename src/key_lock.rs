@@ -0,0 +1,82 @@
+use parking_lot::{Condvar, Mutex};
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+
+// One table per map segment, keyed by the key itself, so only keys in the same
+// segment contend for a table and each key is locked on its own. A slot lives
+// while it is locked or waited on; releasing it wakes one of its own waiters.
+pub(crate) struct KeyLocks<K> {
+    segments: Box<[Mutex<HashMap<K, Slot>>]>,
+}
+
+struct Slot {
+    locked: bool,
+    waiters: usize,
+    released: Arc<Condvar>,
+}
+
+impl<K: Eq + Hash> KeyLocks<K> {
+    pub(crate) fn new(segments: usize) -> Self {
+        KeyLocks {
+            segments: (0..segments).map(|_| Mutex::new(HashMap::new())).collect(),
+        }
+    }
+
+    pub(crate) fn lock<Q>(&self, segment: usize, key: &Q) -> KeyLockGuard<'_, K>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash + ToOwned<Owned = K>,
+    {
+        let mut slots = self.segments[segment].lock();
+        if !slots.contains_key(key) {
+            slots.insert(
+                key.to_owned(),
+                Slot {
+                    locked: false,
+                    waiters: 0,
+                    released: Arc::new(Condvar::new()),
+                },
+            );
+        }
+        loop {
+            let slot = slots.get_mut(key).unwrap();
+            if !slot.locked {
+                slot.locked = true;
+                return KeyLockGuard {
+                    locks: self,
+                    segment,
+                    key: key.to_owned(),
+                };
+            }
+            slot.waiters += 1;
+            let released = slot.released.clone();
+            released.wait(&mut slots);
+            slots.get_mut(key).unwrap().waiters -= 1;
+        }
+    }
+}
+
+/// Exclusive ownership of one key's slot, held until dropped. The lock is
+/// advisory: it serializes `lock_key` callers but does not stop other map
+/// methods from touching the key.
+pub struct KeyLockGuard<'a, K: 'a + Eq + Hash> {
+    locks: &'a KeyLocks<K>,
+    segment: usize,
+    key: K,
+}
+
+impl<'a, K: Eq + Hash> Drop for KeyLockGuard<'a, K> {
+    fn drop(&mut self) {
+        let mut slots = self.locks.segments[self.segment].lock();
+        let slot = slots.get_mut(&self.key).unwrap();
+        slot.locked = false;
+        if slot.waiters == 0 {
+            slots.remove(&self.key);
+        } else {
+            slot.released.notify_one();
+        }
+    }
+}
@@ -1,25 +1,194 @@
-extern crate owning_ref;
+#[cfg(feature = "numa")]
+extern crate libc;
+#[cfg(feature = "guard-timing")]
+extern crate log;
+#[cfg(feature = "deflate")]
+extern crate miniz_oxide;
 extern crate parking_lot;
+#[cfg(feature = "proptest")]
+extern crate proptest;
+#[cfg(feature = "quickcheck")]
+extern crate quickcheck;
+#[cfg(feature = "rand")]
+extern crate rand;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(feature = "deadlock-detection")]
+extern crate thread_id;
 
-use owning_ref::{OwningRef, OwningRefMut};
 use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 use std::borrow::Borrow;
 use std::cmp::{Eq, PartialEq};
-use std::collections::hash_map::{HashMap, RandomState};
+use std::collections::hash_map::{Entry, HashMap, RandomState};
+use std::collections::TryReserveError;
+use std::convert::Infallible;
 use std::default::Default;
 use std::fmt::{self, Debug, Formatter};
-use std::hash::{BuildHasher, Hash, Hasher};
+use std::hash::{BuildHasher, Hash};
 use std::iter::FlatMap;
+use std::marker::PhantomData;
+use std::mem::{self, ManuallyDrop};
 use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 use std::vec;
 
+mod affinity;
+#[cfg(feature = "quickcheck")]
+mod arbitrary;
+mod clock;
+mod codec;
+#[cfg(feature = "deflate")]
+mod compression;
+#[cfg(feature = "deadlock-detection")]
+mod deadlock;
+mod entry_ref;
+mod error;
+#[cfg(feature = "guard-timing")]
+mod guard_timing;
+mod int_map;
+mod key_lock;
+mod loading_cache;
+#[cfg(feature = "lock-stats")]
+mod lock_stats;
+mod maintenance;
+#[cfg(feature = "entry-metadata")]
+mod metadata;
+mod nested;
+mod once_map;
+mod oplog;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod pin;
+mod query;
+mod scan;
+mod scoped;
+mod segment;
+mod snapshot;
+#[cfg(feature = "proptest")]
+mod strategy;
+mod string_map;
+mod tenant;
+mod type_map;
+mod write_buffer;
+
+pub use affinity::AffinityMap;
+pub use clock::{Clock, MockClock, SystemClock};
+pub use codec::{CodecMap, ValueCodec};
+#[cfg(feature = "deflate")]
+pub use compression::{CompressionStats, DeflateCodec};
+#[cfg(feature = "deadlock-detection")]
+pub use deadlock::{check_deadlocks, DeadlockedThread, SegmentId};
+pub use entry_ref::{EntryRef, OccupiedEntryRef, VacantEntryRef};
+pub use error::{Error, GetOrInsertError, InsertError, SequenceError};
+#[cfg(feature = "guard-timing")]
+pub use guard_timing::{set_guard_hold_threshold, set_panic_on_long_hold};
+pub use int_map::{ConcurrentIntMap, IntKey, IntReadGuard, IntWriteGuard};
+pub use key_lock::KeyLockGuard;
+pub use loading_cache::{CacheStats, LoadingCache};
+#[cfg(feature = "lock-stats")]
+pub use lock_stats::SegmentLockStats;
+pub use maintenance::{Maintain, MaintenanceHandle};
+#[cfg(feature = "entry-metadata")]
+pub use metadata::EntryMetadata;
+pub use once_map::OnceMap;
+pub use oplog::{OpLog, Operation};
+pub use pin::PinGuard;
+pub use scan::Cursor;
+pub use scoped::Scoped;
+pub use snapshot::{diff, Diff};
+#[cfg(feature = "proptest")]
+pub use strategy::{map_strategy, set_strategy};
+pub use string_map::{ConcurrentStringMap, SmallString};
+pub use tenant::TenantMap;
+pub use type_map::{ConcurrentTypeMap, TypeReadGuard, TypeWriteGuard};
+pub use write_buffer::WriteBuffer;
+
+use key_lock::KeyLocks;
+use pin::Pins;
+use segment::{IntoEntries, Segment, Slot};
+
 const DEFAULT_INITIAL_CAPACITY: usize = 64;
 const DEFAULT_SEGMENT_COUNT: usize = 16;
 
 pub struct ConcurrentHashMap<K, V, B = RandomState> {
-    segments: Vec<RwLock<HashMap<K, V, B>>>,
+    segments: Vec<RwLock<Segment<K, V, B>>>,
     hash_builder: B,
+    observer: Option<ObserverHook<K, V>>,
+    key_locks: KeyLocks<K>,
+    segment_capacity: Option<usize>,
+    pins: Pins,
+    closed: AtomicBool,
+    #[cfg(feature = "entry-metadata")]
+    clock: Arc<dyn Clock>,
+    #[cfg(feature = "lock-stats")]
+    lock_stats: Box<[lock_stats::LockCounters]>,
+}
+
+/// Receives a notification for every entry the map's own methods insert, replace
+/// or remove, including the bulk operations such as `import_segment`,
+/// `export_segment`, `take` and `close`.
+///
+/// Hooks are called after the segment lock has been released, with copies of the
+/// affected key and value, so an observer may freely call back into the map.
+/// Methods that insert and hand back a guard report the insert when the guard is
+/// dropped, with the value it was left at. The only changes not reported are
+/// in-place modifications of a value that stays in the map: through a
+/// `WriteGuard` on an existing entry (from `get_mut` and its variants,
+/// `get_or_insert*` or `entry_ref`), through `get_mut_unlocked` or
+/// `iter_mut_unlocked`, or by the `retain_mut` and `and_modify` closures.
+pub trait Observer<K, V>: Send + Sync {
+    fn on_insert(&self, _key: &K, _value: &V) {}
+    fn on_update(&self, _key: &K, _value: &V) {}
+    fn on_remove(&self, _key: &K, _value: &V) {}
+}
+
+impl<K, V, O: Observer<K, V> + ?Sized> Observer<K, V> for Arc<O> {
+    fn on_insert(&self, key: &K, value: &V) {
+        (**self).on_insert(key, value)
+    }
+
+    fn on_update(&self, key: &K, value: &V) {
+        (**self).on_update(key, value)
+    }
+
+    fn on_remove(&self, key: &K, value: &V) {
+        (**self).on_remove(key, value)
+    }
+}
+
+struct ObserverHook<K, V> {
+    observer: Box<dyn Observer<K, V>>,
+    clone_key: fn(&K) -> K,
+    clone_value: fn(&V) -> V,
+}
+
+enum Mutation<K, V> {
+    Insert(K, V),
+    Update(K, V),
+}
+
+// An insert made through a guard-returning method, reported once the guard
+// has released its lock.
+struct PendingMutation<'a, K: 'a, V: 'a> {
+    hook: &'a ObserverHook<K, V>,
+    key: K,
+    created: bool,
+}
+
+impl<K, V> ObserverHook<K, V> {
+    fn capture(&self, key: &K, value: &V) -> (K, V) {
+        ((self.clone_key)(key), (self.clone_value)(value))
+    }
+
+    fn notify(&self, mutation: Mutation<K, V>) {
+        match mutation {
+            Mutation::Insert(k, v) => self.observer.on_insert(&k, &v),
+            Mutation::Update(k, v) => self.observer.on_update(&k, &v),
+        }
+    }
 }
 
 impl<K: Eq + Hash, V> ConcurrentHashMap<K, V, RandomState> {
@@ -30,64 +199,412 @@ impl<K: Eq + Hash, V> ConcurrentHashMap<K, V, RandomState> {
 
 impl<K: Eq + Hash, V, B: BuildHasher + Default> ConcurrentHashMap<K, V, B> {
     #[inline]
+    /// Inserts `key`, returning the value it replaced. Once the map is closed
+    /// the entry is dropped instead; use `try_insert` to get it back.
     pub fn insert(&self, key: K, value: V) -> Option<V> {
+        self.try_insert(key, value).unwrap_or(None)
+    }
+
+    /// Like `insert`, but hands the entry back if the map has been closed.
+    pub fn try_insert(&self, key: K, value: V) -> Result<Option<V>, InsertError<K, V>> {
+        let hash = self.hash(&key);
+        let segment_index = self.get_segment(hash);
+        let (previous, mutation) = {
+            let mut segment = self.write_segment(segment_index);
+            if self.is_closed() {
+                return Err(InsertError::new(Error::Closed, key, value));
+            }
+            self.insert_locked(&mut segment, key, value)
+        };
+        self.notify(mutation);
+        Ok(previous)
+    }
+
+    /// Inserts only if the key's segment is below its share of the limit set
+    /// with `with_capacity_limit`, handing the entry back otherwise. Replacing
+    /// an existing key always succeeds, as does any insert into a map without a
+    /// limit.
+    pub fn try_insert_within_capacity(
+        &self,
+        key: K,
+        value: V,
+    ) -> Result<Option<V>, InsertError<K, V>> {
         let hash = self.hash(&key);
         let segment_index = self.get_segment(hash);
-        self.segments[segment_index].write().insert(key, value)
+        let (previous, mutation) = {
+            let mut segment = self.write_segment(segment_index);
+            if self.is_closed() {
+                return Err(InsertError::new(Error::Closed, key, value));
+            }
+            let full = self
+                .segment_capacity
+                .is_some_and(|limit| segment.len() >= limit);
+            if full && !segment.contains_key(&key) {
+                return Err(InsertError::new(Error::CapacityExceeded, key, value));
+            }
+            self.insert_locked(&mut segment, key, value)
+        };
+        self.notify(mutation);
+        Ok(previous)
+    }
+
+    /// Inserts `key` and returns the value it replaced along with a guard on the
+    /// new value, holding the segment lock throughout. Hands the entry back if
+    /// the map has been closed.
+    #[allow(clippy::type_complexity)]
+    pub fn insert_and_get(
+        &self,
+        key: K,
+        value: V,
+    ) -> Result<(Option<V>, WriteGuard<'_, K, V, B>), InsertError<K, V>> {
+        let hash = self.hash(&key);
+        let write_lock = self.write_segment(self.get_segment(hash));
+        if self.is_closed() {
+            return Err(InsertError::new(Error::Closed, key, value));
+        }
+        let mut previous = None;
+        let mut pending = None;
+        #[cfg(feature = "entry-metadata")]
+        let mut metadata = None;
+        let inner = write_lock.into_write_guard(|segment| {
+            let created = !segment.contains_key(&key);
+            pending = self.observer.as_ref().map(|hook| PendingMutation {
+                hook,
+                key: (hook.clone_key)(&key),
+                created,
+            });
+            let slot = match segment.table_mut().entry(key) {
+                Entry::Occupied(entry) => {
+                    let slot = entry.into_mut();
+                    previous = Some(mem::replace(&mut slot.value, value));
+                    slot
+                }
+                Entry::Vacant(entry) => entry.insert(self.slot(value)),
+            };
+            #[cfg(feature = "entry-metadata")]
+            {
+                metadata = slot.metadata();
+            }
+            &mut slot.value
+        });
+        let guard = inner.with_pending(pending);
+        #[cfg(feature = "entry-metadata")]
+        let guard = guard.with_metadata(metadata.unwrap());
+        Ok((previous, guard))
+    }
+
+    /// Returns a guard on `key`'s value, inserting `value` first if the key is
+    /// absent. `value` is dropped if the key already exists. Fails with
+    /// `Error::Closed` if the key is absent and the map has been closed.
+    pub fn get_or_insert(&self, key: K, value: V) -> Result<WriteGuard<'_, K, V, B>, Error> {
+        self.get_or_insert_with(key, || value)
+    }
+
+    /// Like `get_or_insert`, but only builds the value if the key is absent.
+    pub fn get_or_insert_with<F>(
+        &self,
+        key: K,
+        default: F,
+    ) -> Result<WriteGuard<'_, K, V, B>, Error>
+    where
+        F: FnOnce() -> V,
+    {
+        match self.get_or_try_insert_with(key, || Ok::<V, Infallible>(default())) {
+            Ok(guard) => Ok(guard),
+            Err(GetOrInsertError::Closed) => Err(Error::Closed),
+            Err(GetOrInsertError::Failed(never)) => match never {},
+        }
+    }
+
+    /// Like `get_or_insert_with`, but building the value may fail, in which case
+    /// nothing is inserted and the error is returned. `f` runs under the
+    /// segment's write lock. Fails with `GetOrInsertError::Closed`, without
+    /// calling `f`, if the key is absent and the map has been closed.
+    pub fn get_or_try_insert_with<F, E>(
+        &self,
+        key: K,
+        f: F,
+    ) -> Result<WriteGuard<'_, K, V, B>, GetOrInsertError<E>>
+    where
+        F: FnOnce() -> Result<V, E>,
+    {
+        let hash = self.hash(&key);
+        let write_lock = self.write_segment(self.get_segment(hash));
+        let created = !write_lock.contains_key(&key);
+        let mut value = None;
+        let mut pending = None;
+        if created {
+            if self.is_closed() {
+                return Err(GetOrInsertError::Closed);
+            }
+            value = Some(f().map_err(GetOrInsertError::Failed)?);
+            pending = self.observer.as_ref().map(|hook| PendingMutation {
+                hook,
+                key: (hook.clone_key)(&key),
+                created,
+            });
+        }
+        #[cfg(feature = "entry-metadata")]
+        let mut metadata = None;
+        let inner = write_lock.into_write_guard(|segment| {
+            let slot = match segment.table_mut().entry(key) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(self.slot(value.unwrap())),
+            };
+            #[cfg(feature = "entry-metadata")]
+            {
+                metadata = if created {
+                    slot.metadata()
+                } else {
+                    Some(slot.accessed(self.clock.now()))
+                };
+            }
+            &mut slot.value
+        });
+        let guard = inner.with_pending(pending);
+        #[cfg(feature = "entry-metadata")]
+        let guard = guard.with_metadata(metadata.unwrap());
+        Ok(guard)
+    }
+
+    /// Reserves room for `additional` more entries by reserving an even share
+    /// in each segment. The share is an estimate: keys rarely spread perfectly,
+    /// so inserting `additional` entries may still grow the busier segments.
+    pub fn try_reserve(&self, additional: usize) -> Result<(), TryReserveError> {
+        let per_segment = additional.div_ceil(self.segments.len());
+        for i in 0..self.segments.len() {
+            self.write_segment(i).try_reserve(per_segment)?;
+        }
+        Ok(())
     }
 
     #[inline]
-    pub fn contains<Q: ?Sized>(&self, key: &Q) -> bool
+    pub fn contains<Q>(&self, key: &Q) -> bool
     where
         K: Borrow<Q>,
-        Q: Eq + Hash,
+        Q: ?Sized + Eq + Hash,
     {
         let hash = self.hash(key);
         let segment_index = self.get_segment(hash);
-        self.segments[segment_index].read().contains_key(key)
+        self.read_segment(segment_index).contains_key(key)
+    }
+
+    /// Reads `key`'s metadata without counting it as an access.
+    #[cfg(feature = "entry-metadata")]
+    pub fn metadata<Q>(&self, key: &Q) -> Option<EntryMetadata>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        let segment = self.read_segment(self.get_segment(self.hash(key)));
+        segment.metadata(key)
+    }
+
+    /// Clones every entry along with its metadata, oldest first by creation time.
+    /// Each segment is read under its own lock, so this is not an atomic view.
+    #[cfg(feature = "entry-metadata")]
+    pub fn iter_by_age(&self) -> Vec<(K, V, EntryMetadata)>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        let mut entries = self.entries_with_metadata();
+        entries.sort_by_key(|&(_, _, meta)| meta.created);
+        entries
+    }
+
+    /// The `n` least recently accessed entries, coldest first. Entries that
+    /// have never been read count as last accessed when they were created.
+    #[cfg(feature = "entry-metadata")]
+    pub fn coldest(&self, n: usize) -> Vec<(K, V, EntryMetadata)>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        let mut entries = self.entries_with_metadata();
+        entries.sort_by_key(|&(_, _, meta)| meta.last_accessed);
+        entries.truncate(n);
+        entries
     }
 
+    #[cfg(feature = "entry-metadata")]
+    fn entries_with_metadata(&self) -> Vec<(K, V, EntryMetadata)>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        let mut entries = Vec::new();
+        for i in 0..self.segments.len() {
+            let segment = self.read_segment(i);
+            for (k, v, meta) in segment.iter_metadata() {
+                if let Some(meta) = meta {
+                    entries.push((k.clone(), v.clone(), meta));
+                }
+            }
+        }
+        entries
+    }
+
+    /// Removes `key`, unless it is pinned, in which case it is left in place and
+    /// `None` is returned.
     #[inline]
-    pub fn remove<Q: ?Sized>(&self, key: &Q) -> Option<V>
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
     where
         K: Borrow<Q>,
-        Q: Eq + Hash,
+        Q: ?Sized + Eq + Hash,
     {
         let hash = self.hash(key);
         let segment_index = self.get_segment(hash);
-        self.segments[segment_index].write().remove(key)
+        let removed = {
+            let mut segment = self.write_segment(segment_index);
+            if self.pins.any() && segment.is_pinned(key) {
+                return None;
+            }
+            segment.remove_entry(key)
+        };
+        let hook = match self.observer {
+            Some(ref hook) => hook,
+            None => return removed.map(|(_, v)| v),
+        };
+        removed.map(|(k, v)| {
+            hook.observer.on_remove(&k, &v);
+            v
+        })
     }
 
     #[inline]
-    pub fn get<Q: ?Sized>(&self, key: &Q) -> Option<ReadGuard<K, V, B>>
+    pub fn get<Q>(&self, key: &Q) -> Option<ReadGuard<'_, K, V, B>>
     where
         K: Borrow<Q>,
-        Q: Eq + Hash,
+        Q: ?Sized + Eq + Hash,
     {
         let hash = self.hash(key);
-        let segment_index = self.get_segment(hash);
-        let read_lock = self.segments[segment_index].read();
-        let owning_ref = OwningRef::new(read_lock);
-        owning_ref
-            .try_map(|segment| segment.get(key).ok_or(()))
-            .ok()
-            .map(|inner| ReadGuard { inner })
+        let read_lock = self.read_segment(self.get_segment(hash));
+        self.read_guard(read_lock, key)
+    }
+
+    /// Like `get`, but fails with `Error::WouldBlock` instead of waiting for a
+    /// writer to release the key's segment.
+    pub fn try_get<Q>(&self, key: &Q) -> Result<Option<ReadGuard<'_, K, V, B>>, Error>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        let hash = self.hash(key);
+        match self.segments[self.get_segment(hash)].try_read() {
+            Some(read_lock) => Ok(self.read_guard(read_lock, key)),
+            None => Err(Error::WouldBlock),
+        }
+    }
+
+    /// Like `get`, but gives up with `Error::Timeout` after waiting `timeout`.
+    pub fn get_timeout<Q>(
+        &self,
+        key: &Q,
+        timeout: Duration,
+    ) -> Result<Option<ReadGuard<'_, K, V, B>>, Error>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        let hash = self.hash(key);
+        match self.segments[self.get_segment(hash)].try_read_for(timeout) {
+            Some(read_lock) => Ok(self.read_guard(read_lock, key)),
+            None => Err(Error::Timeout),
+        }
     }
 
     #[inline]
-    pub fn get_mut<Q: ?Sized>(&self, key: &Q) -> Option<WriteGuard<K, V, B>>
+    pub fn get_mut<Q>(&self, key: &Q) -> Option<WriteGuard<'_, K, V, B>>
     where
         K: Borrow<Q>,
-        Q: Eq + Hash,
+        Q: ?Sized + Eq + Hash,
     {
         let hash = self.hash(key);
-        let segment_index = self.get_segment(hash);
-        let write_lock = self.segments[segment_index].write();
-        let owning_ref = OwningRefMut::new(write_lock);
-        owning_ref
-            .try_map_mut(|segment| segment.get_mut(key).ok_or(()))
-            .ok()
-            .map(|inner| WriteGuard { inner })
+        let write_lock = self.write_segment(self.get_segment(hash));
+        self.write_guard(write_lock, key)
+    }
+
+    /// Returns a guard on `key`'s value, inserting `V::default()` first if the
+    /// key is absent. See `get_or_insert_with`.
+    pub fn get_mut_or_insert_default(&self, key: K) -> Result<WriteGuard<'_, K, V, B>, Error>
+    where
+        V: Default,
+    {
+        self.get_or_insert_with(key, V::default)
+    }
+
+    /// Like `get_mut`, but fails with `Error::WouldBlock` instead of waiting for
+    /// the key's segment.
+    pub fn try_get_mut<Q>(&self, key: &Q) -> Result<Option<WriteGuard<'_, K, V, B>>, Error>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        let hash = self.hash(key);
+        match self.try_write_segment(self.get_segment(hash), None) {
+            Some(write_lock) => Ok(self.write_guard(write_lock, key)),
+            None => Err(Error::WouldBlock),
+        }
+    }
+
+    /// Like `get_mut`, but gives up with `Error::Timeout` after waiting `timeout`.
+    pub fn get_mut_timeout<Q>(
+        &self,
+        key: &Q,
+        timeout: Duration,
+    ) -> Result<Option<WriteGuard<'_, K, V, B>>, Error>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        let hash = self.hash(key);
+        match self.try_write_segment(self.get_segment(hash), Some(timeout)) {
+            Some(write_lock) => Ok(self.write_guard(write_lock, key)),
+            None => Err(Error::Timeout),
+        }
+    }
+
+    #[inline]
+    fn read_guard<'a, Q>(
+        &'a self,
+        read_lock: RwLockReadGuard<'a, Segment<K, V, B>>,
+        key: &Q,
+    ) -> Option<ReadGuard<'a, K, V, B>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        if !read_lock.contains_key(key) {
+            return None;
+        }
+        #[cfg(feature = "entry-metadata")]
+        let metadata = read_lock.accessed(key, self.clock.now()).unwrap();
+        let guard = ReadGuard::new(RwLockReadGuard::map(read_lock, |segment| &segment[key]));
+        #[cfg(feature = "entry-metadata")]
+        let guard = guard.with_metadata(metadata);
+        Some(guard)
+    }
+
+    #[inline]
+    fn write_guard<'a, Q>(
+        &'a self,
+        write_lock: SegmentWriteGuard<'a, K, V, B>,
+        key: &Q,
+    ) -> Option<WriteGuard<'a, K, V, B>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        if !write_lock.contains_key(key) {
+            return None;
+        }
+        #[cfg(feature = "entry-metadata")]
+        let metadata = write_lock.accessed(key, self.clock.now()).unwrap();
+        let guard = write_lock.into_write_guard(|segment| segment.get_mut(key).unwrap());
+        #[cfg(feature = "entry-metadata")]
+        let guard = guard.with_metadata(metadata);
+        Some(guard)
     }
 
     pub fn with_options(capacity: usize, hash_builder: B, concurrency_level: usize) -> Self {
@@ -95,48 +612,641 @@ impl<K: Eq + Hash, V, B: BuildHasher + Default> ConcurrentHashMap<K, V, B> {
         let per_segment_capacity = (capacity / concurrency_level).next_power_of_two();
         let mut segments = Vec::with_capacity(concurrency_level);
         for _ in 0..concurrency_level {
-            segments.push(RwLock::new(HashMap::with_capacity_and_hasher(
-                per_segment_capacity,
-                <B as Default>::default(),
+            segments.push(RwLock::new(Segment::new(
+                HashMap::with_capacity_and_hasher(per_segment_capacity, <B as Default>::default()),
             )))
         }
         ConcurrentHashMap {
             hash_builder,
             segments,
+            observer: None,
+            key_locks: KeyLocks::new(concurrency_level),
+            segment_capacity: None,
+            pins: Pins::new(),
+            closed: AtomicBool::new(false),
+            #[cfg(feature = "entry-metadata")]
+            clock: Arc::new(SystemClock),
+            #[cfg(feature = "lock-stats")]
+            lock_stats: (0..concurrency_level).map(|_| Default::default()).collect(),
         }
     }
 
+    /// Sets the clock used to timestamp entry creation and access.
+    #[cfg(feature = "entry-metadata")]
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Caps the map at `limit` entries for `try_insert_within_capacity`, split
+    /// evenly over the segments and rounded up, so each segment holds at most
+    /// its share. Other inserts ignore the limit.
+    pub fn with_capacity_limit(mut self, limit: usize) -> Self {
+        self.segment_capacity = Some(limit.div_ceil(self.segments.len()));
+        self
+    }
+
+    pub fn with_observer<O>(mut self, observer: O) -> Self
+    where
+        O: Observer<K, V> + 'static,
+        K: Clone,
+        V: Clone,
+    {
+        self.observer = Some(ObserverHook {
+            observer: Box::new(observer),
+            clone_key: K::clone,
+            clone_value: V::clone,
+        });
+        self
+    }
+
+    /// Once the map is closed this does nothing; use `try_insert_or_update` to
+    /// find out.
     #[inline]
     pub fn insert_or_update<F, G>(&self, key: K, insert: F, update: G)
+    where
+        F: FnOnce() -> V,
+        G: FnOnce(&mut V),
+    {
+        self.try_insert_or_update(key, insert, update).unwrap_or(())
+    }
+
+    /// Like `insert_or_update`, but fails with `Error::Closed`, calling neither
+    /// closure, if the map has been closed.
+    pub fn try_insert_or_update<F, G>(&self, key: K, insert: F, update: G) -> Result<(), Error>
     where
         F: FnOnce() -> V,
         G: FnOnce(&mut V),
     {
         let hash = self.hash(&key);
         let segment_index = self.get_segment(hash);
-        let mut segment_lock = self.segments[segment_index].write();
-        segment_lock
-            .entry(key)
-            .and_modify(update)
-            .or_insert_with(insert);
+        let mut segment_lock = self.write_segment(segment_index);
+        if self.is_closed() {
+            return Err(Error::Closed);
+        }
+        let hook = self.observer.as_ref();
+        let mutation = match segment_lock.table_mut().entry(key) {
+            Entry::Occupied(mut entry) => {
+                update(&mut entry.get_mut().value);
+                hook.map(|hook| {
+                    let (k, v) = hook.capture(entry.key(), &entry.get().value);
+                    Mutation::Update(k, v)
+                })
+            }
+            Entry::Vacant(entry) => match hook {
+                Some(hook) => {
+                    let k = (hook.clone_key)(entry.key());
+                    let v = (hook.clone_value)(&entry.insert(self.slot(insert())).value);
+                    Some(Mutation::Insert(k, v))
+                }
+                None => {
+                    entry.insert(self.slot(insert()));
+                    None
+                }
+            },
+        };
+        drop(segment_lock);
+        self.notify(mutation);
+        Ok(())
+    }
+
+    /// Applies `(sequence, operation)` pairs, as returned by `OpLog::ops_since`,
+    /// in order, and returns the last sequence number applied. Stops with an
+    /// error at the first pair whose sequence number does not follow the one
+    /// before it; the pairs before it stay applied.
+    pub fn apply<I>(&self, ops: I) -> Result<Option<u64>, SequenceError>
+    where
+        I: IntoIterator<Item = (u64, Operation<K, V>)>,
+    {
+        let mut last = None;
+        for (sequence, op) in ops {
+            if let Some(previous) = last {
+                if sequence != previous + 1 {
+                    return Err(SequenceError {
+                        expected: previous + 1,
+                        found: sequence,
+                    });
+                }
+            }
+            last = Some(sequence);
+            match op {
+                Operation::Insert(key, value) => {
+                    self.insert(key, value);
+                }
+                Operation::Remove(key) => {
+                    self.remove(&key);
+                }
+            }
+        }
+        Ok(last)
+    }
+
+    pub fn snapshot(&self) -> HashMap<K, V, B>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        let mut snapshot = HashMap::with_hasher(B::default());
+        for i in 0..self.segments.len() {
+            let segment = self.read_segment(i);
+            snapshot.extend(segment.iter().map(|(k, v)| (k.clone(), v.clone())));
+        }
+        snapshot
+    }
+
+    /// Compares the live map against an earlier snapshot. Each segment is read
+    /// under its own lock, so mutations racing with the diff may or may not be seen.
+    pub fn diff<S: BuildHasher>(&self, snapshot: &HashMap<K, V, S>) -> Diff<K>
+    where
+        K: Clone,
+        V: PartialEq,
+    {
+        let mut diff = Diff::empty();
+        for i in 0..self.segments.len() {
+            for (k, v) in self.read_segment(i).iter() {
+                match snapshot.get(k) {
+                    None => diff.added.push(k.clone()),
+                    Some(old_v) if old_v != v => diff.changed.push(k.clone()),
+                    Some(_) => {}
+                }
+            }
+        }
+        diff.removed
+            .extend(snapshot.keys().filter(|k| !self.contains(*k)).cloned());
+        diff
+    }
+
+    /// Copies the map, sharing each segment table with the original; a table is
+    /// only copied when one side first writes to it. Segments are shared one
+    /// lock at a time, so each segment is copied as of a single moment but the
+    /// copy as a whole is not, as with `snapshot`.
+    ///
+    /// The observer is not carried over to the copy. With entry-metadata,
+    /// tables cannot be shared, so every table is copied eagerly, each under
+    /// its own segment's lock.
+    pub fn cow_clone(&self) -> Self
+    where
+        K: Clone,
+        V: Clone,
+        B: Clone,
+    {
+        let pinned = self.pins.any();
+        let segments = (0..self.segments.len())
+            .map(|i| RwLock::new(self.write_segment(i).share(pinned)))
+            .collect();
+        self.with_segments(segments)
+    }
+
+    // A map around `segments`, laid out like this one, with the same hasher and
+    // clock but no observer.
+    fn with_segments(&self, segments: Vec<RwLock<Segment<K, V, B>>>) -> Self
+    where
+        B: Clone,
+    {
+        ConcurrentHashMap {
+            hash_builder: self.hash_builder.clone(),
+            observer: None,
+            key_locks: KeyLocks::new(segments.len()),
+            segment_capacity: self.segment_capacity,
+            pins: Pins::new(),
+            closed: AtomicBool::new(false),
+            #[cfg(feature = "entry-metadata")]
+            clock: self.clock.clone(),
+            #[cfg(feature = "lock-stats")]
+            lock_stats: (0..segments.len()).map(|_| Default::default()).collect(),
+            segments,
+        }
+    }
+
+    /// Blocks until no other caller holds `key`'s lock, whether or not the key is
+    /// present. Each key has its own lock, so holding several keys' locks at
+    /// once is fine as long as callers take them in a consistent order; locking
+    /// a key this thread already holds deadlocks. No segment lock is held while
+    /// the guard is alive, so the map can be used normally, including for this
+    /// key. The guard owns a copy of the key, built with `to_owned`.
+    pub fn lock_key<Q>(&self, key: &Q) -> KeyLockGuard<'_, K>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash + ToOwned<Owned = K>,
+    {
+        self.key_locks.lock(self.get_segment(self.hash(key)), key)
+    }
+
+    pub fn len(&self) -> usize {
+        (0..self.segments.len())
+            .map(|i| self.read_segment(i).len())
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        (0..self.segments.len()).all(|i| self.read_segment(i).is_empty())
+    }
+
+    /// Lock counters for each segment, in segment order, since the map was
+    /// created. Time spent in `WriteGuard`s counts as hold time of their segment.
+    #[cfg(feature = "lock-stats")]
+    pub fn lock_stats(&self) -> Vec<SegmentLockStats> {
+        self.lock_stats.iter().map(|c| c.snapshot()).collect()
+    }
+
+    /// Releases excess capacity in every segment, one lock at a time. Segments
+    /// still shared with a `cow_clone` are skipped.
+    pub fn shrink_to_fit(&self) {
+        for i in 0..self.segments.len() {
+            self.write_segment(i).shrink_to_fit();
+        }
+    }
+
+    pub fn segment_count(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub fn segment_index<Q>(&self, key: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.get_segment(self.hash(key))
+    }
+
+    /// Removes and returns every unpinned entry stored in segment `index`. The
+    /// entries are reported to the observer as removals.
+    pub fn export_segment(&self, index: usize) -> Vec<(K, V)> {
+        let exported: Vec<_> = {
+            let mut segment = self.write_segment(index);
+            if !self.pins.any() {
+                segment.drain().collect()
+            } else {
+                segment.extract_unpinned(|_, _| true)
+            }
+        };
+        if let Some(ref hook) = self.observer {
+            for (k, v) in &exported {
+                hook.observer.on_remove(k, v);
+            }
+        }
+        exported
+    }
+
+    /// Empties the map and returns its former contents as a map with the same
+    /// hasher and segment layout, so the original stays usable. Each segment's
+    /// table is swapped out under its lock in turn; an entry inserted meanwhile
+    /// ends up in exactly one of the two maps. Pinned entries are left in place,
+    /// and the returned entries are reported to the observer as removals.
+    pub fn take(&self) -> Self
+    where
+        B: Clone,
+    {
+        let segments = (0..self.segments.len())
+            .map(|i| {
+                let taken = {
+                    let mut segment = self.write_segment(i);
+                    let empty = Segment::new(HashMap::with_hasher(B::default()));
+                    if !self.pins.any() {
+                        mem::replace(&mut *segment, empty)
+                    } else {
+                        let mut taken = empty;
+                        segment.move_unpinned(&mut taken);
+                        taken
+                    }
+                };
+                if let Some(ref hook) = self.observer {
+                    for (k, v) in taken.iter() {
+                        hook.observer.on_remove(k, v);
+                    }
+                }
+                RwLock::new(taken)
+            })
+            .collect();
+        self.with_segments(segments)
+    }
+
+    /// Calls `f` on every entry, one segment lock at a time, and removes the
+    /// entries for which it returns `false`. `f` may modify values it keeps.
+    /// Pinned entries are passed to `f` but never removed. Removals are reported
+    /// to the observer; modifications are not. Returns how many were removed.
+    pub fn retain_mut<F>(&self, mut f: F) -> usize
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        self.remove_from_segments(|segment| segment.extract_unpinned_mut(|k, v| !f(k, v)))
+    }
+
+    // Removes the unpinned entries matching `pred`, one segment lock at a time,
+    // and reports them to the observer once each segment's lock is released.
+    pub(crate) fn remove_where<F>(&self, mut pred: F) -> usize
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        self.remove_from_segments(|segment| segment.extract_unpinned(&mut pred))
+    }
+
+    fn remove_from_segments<F>(&self, mut extract: F) -> usize
+    where
+        F: FnMut(&mut Segment<K, V, B>) -> Vec<(K, V)>,
+    {
+        let mut count = 0;
+        for i in 0..self.segments.len() {
+            let removed = extract(&mut self.write_segment(i));
+            count += removed.len();
+            if let Some(ref hook) = self.observer {
+                for (k, v) in &removed {
+                    hook.observer.on_remove(k, v);
+                }
+            }
+        }
+        count
+    }
+
+    /// Shuts the map down: from now on inserts are rejected, then each segment
+    /// in turn waits for its outstanding guards, is drained, and has its entries
+    /// reported to the observer as removals. Reads are served throughout, so a
+    /// segment's entries stay visible until it is drained. Pinned entries are
+    /// left in place. Returns the drained entries.
+    ///
+    /// A rejected write never panics. Methods that add entries fail with
+    /// `Error::Closed`, handing back the entry where they take one by value,
+    /// except `insert` and `insert_or_update`, which drop the write; their
+    /// `try_insert` and `try_insert_or_update` forms report it.
+    pub fn close(&self) -> Vec<(K, V)> {
+        self.closed.store(true, Ordering::Release);
+        let mut drained = Vec::new();
+        for i in 0..self.segments.len() {
+            let start = drained.len();
+            {
+                let mut segment = self.write_segment(i);
+                if !self.pins.any() {
+                    drained.extend(segment.drain());
+                } else {
+                    drained.extend(segment.extract_unpinned(|_, _| true));
+                }
+            }
+            if let Some(ref hook) = self.observer {
+                for (k, v) in &drained[start..] {
+                    hook.observer.on_remove(k, v);
+                }
+            }
+        }
+        drained
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    /// Like `get_mut`, but borrows the map exclusively instead of taking the
+    /// segment lock.
+    pub fn get_mut_unlocked<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        let index = self.get_segment(self.hash(key));
+        let segment = self.segments[index].get_mut();
+        #[cfg(feature = "entry-metadata")]
+        segment.accessed(key, self.clock.now());
+        segment.get_mut(key)
+    }
+
+    /// Iterates over the entries in place, without taking any locks.
+    pub fn iter_mut_unlocked(&mut self) -> impl Iterator<Item = (&K, &mut V)> {
+        self.segments
+            .iter_mut()
+            .flat_map(|segment| segment.get_mut().iter_mut())
+    }
+
+    /// Removes every entry without taking any locks, reporting each to the
+    /// observer as a removal.
+    pub fn clear_unlocked(&mut self) {
+        for segment in &mut self.segments {
+            let segment = segment.get_mut();
+            match self.observer {
+                Some(ref hook) => {
+                    for (k, v) in segment.drain() {
+                        hook.observer.on_remove(&k, &v);
+                    }
+                }
+                None => segment.clear(),
+            }
+        }
+    }
+
+    /// Consumes the map, keeping its sharding: entry `i` of the result holds the
+    /// contents of segment `i`, as addressed by `segment_index`.
+    pub fn into_segment_vecs(self) -> Vec<Vec<(K, V)>> {
+        self.segments
+            .into_iter()
+            .map(|segment| segment.into_inner().into_entries().collect())
+            .collect()
+    }
+
+    /// Iterates over copies of the entries while other threads keep using the map.
+    ///
+    /// Segments are visited one at a time: each is copied under its read lock,
+    /// which is released before any of its entries are yielded. The iterator
+    /// therefore never holds a lock between calls to `next`, and the caller may
+    /// write to the map mid-iteration. Every key is yielded at most once. Keys
+    /// present for the whole iteration are yielded exactly once; keys inserted
+    /// or removed during it may or may not be.
+    pub fn iter(&self) -> Iter<'_, K, V, B>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        Iter {
+            map: self,
+            next_segment: 0,
+            current: Vec::new().into_iter(),
+        }
+    }
+
+    /// Consumes the map into its entries ordered by key.
+    pub fn into_sorted_vec(self) -> Vec<(K, V)>
+    where
+        K: Ord,
+    {
+        let mut entries: Vec<_> = self.into_iter().collect();
+        entries.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        entries
+    }
+
+    /// Clones the entries out ordered by key. Segments are copied one lock at a
+    /// time, as with `snapshot`.
+    pub fn to_sorted_vec(&self) -> Vec<(K, V)>
+    where
+        K: Ord + Clone,
+        V: Clone,
+    {
+        let mut entries = Vec::with_capacity(self.len());
+        for i in 0..self.segments.len() {
+            let segment = self.read_segment(i);
+            entries.extend(segment.iter().map(|(k, v)| (k.clone(), v.clone())));
+        }
+        entries.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        entries
+    }
+
+    /// Pins `key` so that it cannot be removed while the guard is alive. Returns
+    /// `None` if the key is absent.
+    pub fn pin<Q>(&self, key: &Q) -> Option<PinGuard<'_>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        let mut segment = self.write_segment(self.get_segment(self.hash(key)));
+        segment.pin(key).map(|count| self.pins.pin(count))
+    }
+
+    /// Inserts `entries` into segment `index` under a single lock acquisition,
+    /// reporting them to the observer once it is released. Entries whose keys do
+    /// not hash to `index` in this map, or all of them if the map is closed, are
+    /// handed back.
+    pub fn import_segment<I>(&self, index: usize, entries: I) -> Vec<(K, V)>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let mut rejected = Vec::new();
+        let mut mutations = Vec::new();
+        {
+            let mut segment = self.write_segment(index);
+            if self.is_closed() {
+                rejected.extend(entries);
+                return rejected;
+            }
+            for (k, v) in entries {
+                let hash = self.hash(&k);
+                if self.get_segment(hash) != index {
+                    rejected.push((k, v));
+                } else {
+                    mutations.extend(self.insert_locked(&mut segment, k, v).1);
+                }
+            }
+        }
+        for mutation in mutations {
+            self.notify(Some(mutation));
+        }
+        rejected
+    }
+
+    #[inline]
+    fn insert_locked(
+        &self,
+        segment: &mut Segment<K, V, B>,
+        key: K,
+        value: V,
+    ) -> (Option<V>, Option<Mutation<K, V>>) {
+        match self.observer {
+            None => (segment.insert(key, self.slot(value)), None),
+            Some(ref hook) => match segment.table_mut().entry(key) {
+                Entry::Occupied(mut entry) => {
+                    let previous = mem::replace(&mut entry.get_mut().value, value);
+                    let (k, v) = hook.capture(entry.key(), &entry.get().value);
+                    (Some(previous), Some(Mutation::Update(k, v)))
+                }
+                Entry::Vacant(entry) => {
+                    let k = (hook.clone_key)(entry.key());
+                    let v = (hook.clone_value)(&entry.insert(self.slot(value)).value);
+                    (None, Some(Mutation::Insert(k, v)))
+                }
+            },
+        }
+    }
+
+    #[inline]
+    fn notify(&self, mutation: Option<Mutation<K, V>>) {
+        if let (Some(hook), Some(mutation)) = (self.observer.as_ref(), mutation) {
+            hook.notify(mutation);
+        }
     }
 
     #[inline]
-    fn hash<Q: ?Sized>(&self, key: &Q) -> u64
+    fn hash<Q>(&self, key: &Q) -> u64
     where
         K: Borrow<Q>,
-        Q: Eq + Hash,
+        Q: ?Sized + Eq + Hash,
     {
-        let mut hasher = self.hash_builder.build_hasher();
-        key.hash(&mut hasher);
-        hasher.finish()
+        self.hash_builder.hash_one(key)
     }
 
     #[inline(always)]
     fn get_segment(&self, hash: u64) -> usize {
         let shift_size =
             (std::mem::size_of::<usize>() * 8) - self.segments.len().trailing_zeros() as usize;
-        (hash as usize >> shift_size) & (self.segments.len() - 1)
+        // A single segment shifts by the full width, which `>>` rejects.
+        (hash as usize).checked_shr(shift_size as u32).unwrap_or(0) & (self.segments.len() - 1)
+    }
+}
+
+impl<K, V, B> ConcurrentHashMap<K, V, B> {
+    #[inline]
+    fn read_segment(&self, index: usize) -> RwLockReadGuard<'_, Segment<K, V, B>> {
+        #[cfg(feature = "deadlock-detection")]
+        let _waiting = deadlock::Waiting::new(self as *const Self as usize, index);
+        #[cfg(feature = "lock-stats")]
+        let lock = self.segments[index].try_read().unwrap_or_else(|| {
+            self.lock_stats[index].contended_read();
+            self.lock_stats[index].wait(|| self.segments[index].read())
+        });
+        #[cfg(not(feature = "lock-stats"))]
+        let lock = self.segments[index].read();
+        lock
+    }
+
+    #[inline]
+    fn write_segment(&self, index: usize) -> SegmentWriteGuard<'_, K, V, B> {
+        #[cfg(feature = "deadlock-detection")]
+        let _waiting = deadlock::Waiting::new(self as *const Self as usize, index);
+        #[cfg(feature = "lock-stats")]
+        let lock = self.segments[index].try_write().unwrap_or_else(|| {
+            self.lock_stats[index].contended();
+            self.lock_stats[index].wait(|| self.segments[index].write())
+        });
+        #[cfg(not(feature = "lock-stats"))]
+        let lock = self.segments[index].write();
+        self.segment_write_guard(index, lock)
+    }
+
+    // Without a timeout this never waits.
+    fn try_write_segment(
+        &self,
+        index: usize,
+        timeout: Option<Duration>,
+    ) -> Option<SegmentWriteGuard<'_, K, V, B>> {
+        let lock = match timeout {
+            None => self.segments[index].try_write(),
+            Some(timeout) => self.segments[index].try_write_for(timeout),
+        };
+        #[cfg(feature = "lock-stats")]
+        {
+            if lock.is_none() {
+                self.lock_stats[index].contended();
+            }
+        }
+        lock.map(|lock| self.segment_write_guard(index, lock))
+    }
+
+    #[inline]
+    #[cfg_attr(not(feature = "lock-stats"), allow(unused_variables))]
+    fn segment_write_guard<'a>(
+        &'a self,
+        index: usize,
+        lock: RwLockWriteGuard<'a, Segment<K, V, B>>,
+    ) -> SegmentWriteGuard<'a, K, V, B> {
+        SegmentWriteGuard {
+            lock,
+            #[cfg(feature = "lock-stats")]
+            held: self.lock_stats[index].acquired(),
+        }
+    }
+
+    // A slot for a new entry. Every path that adds keys must build it here, so
+    // that with entry-metadata the entry records when it was created.
+    #[inline]
+    fn slot(&self, value: V) -> Slot<V> {
+        let slot = Slot::new(value);
+        #[cfg(feature = "entry-metadata")]
+        let slot = slot.with_meta(self.clock.now());
+        slot
     }
 }
 
@@ -150,6 +1260,19 @@ impl<K: Eq + Hash, V, B: BuildHasher + Default> Default for ConcurrentHashMap<K,
     }
 }
 
+/// Clones are taken with `cow_clone`: the observer is not carried over, and
+/// with entry-metadata every table is copied eagerly.
+impl<K, V, B> Clone for ConcurrentHashMap<K, V, B>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    B: BuildHasher + Default + Clone,
+{
+    fn clone(&self) -> Self {
+        self.cow_clone()
+    }
+}
+
 impl<K, V, B> Debug for ConcurrentHashMap<K, V, B>
 where
     K: Hash + Eq + Debug,
@@ -158,8 +1281,8 @@ where
 {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         write!(f, "ConcurrentHashMap{{")?;
-        for segment in &self.segments {
-            for (k, v) in segment.read().iter() {
+        for i in 0..self.segments.len() {
+            for (k, v) in self.read_segment(i).iter() {
                 write!(f, "{:?}: {:?}, ", k, v)?;
             }
         }
@@ -168,7 +1291,44 @@ where
 }
 
 pub struct ReadGuard<'a, K: 'a, V: 'a, B: 'a> {
-    inner: OwningRef<RwLockReadGuard<'a, HashMap<K, V, B>>, V>,
+    inner: RwLockReadGuard<'a, V>,
+    marker: PhantomData<&'a (K, B)>,
+    #[cfg(feature = "guard-timing")]
+    timer: guard_timing::HoldTimer,
+    #[cfg(feature = "entry-metadata")]
+    metadata: Option<EntryMetadata>,
+}
+
+impl<'a, K: 'a, V: 'a, B: 'a> ReadGuard<'a, K, V, B> {
+    fn new(inner: RwLockReadGuard<'a, V>) -> Self {
+        ReadGuard {
+            inner,
+            marker: PhantomData,
+            #[cfg(feature = "guard-timing")]
+            timer: guard_timing::HoldTimer::start(),
+            #[cfg(feature = "entry-metadata")]
+            metadata: None,
+        }
+    }
+
+    #[cfg(feature = "entry-metadata")]
+    fn with_metadata(mut self, metadata: EntryMetadata) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    /// The entry's timestamps and access count as of this guard's acquisition.
+    #[cfg(feature = "entry-metadata")]
+    pub fn metadata(&self) -> EntryMetadata {
+        self.metadata.unwrap()
+    }
+}
+
+#[cfg(feature = "guard-timing")]
+impl<'a, K: 'a, V: 'a, B: 'a> Drop for ReadGuard<'a, K, V, B> {
+    fn drop(&mut self) {
+        self.timer.finish("ReadGuard");
+    }
 }
 
 impl<'a, K: 'a, V: 'a, B: 'a> Deref for ReadGuard<'a, K, V, B> {
@@ -192,8 +1352,125 @@ impl<'a, K: 'a, V: Debug + 'a, B: 'a> Debug for ReadGuard<'a, K, V, B> {
     }
 }
 
+// A write lock on a whole segment. With lock-stats it records how long the lock
+// was held, or hands that over to the `WriteGuard` it is narrowed into.
+struct SegmentWriteGuard<'a, K: 'a, V: 'a, B: 'a> {
+    lock: RwLockWriteGuard<'a, Segment<K, V, B>>,
+    #[cfg(feature = "lock-stats")]
+    held: lock_stats::Held<'a>,
+}
+
+impl<'a, K: 'a, V: 'a, B: 'a> SegmentWriteGuard<'a, K, V, B> {
+    fn into_write_guard<F>(self, f: F) -> WriteGuard<'a, K, V, B>
+    where
+        F: FnOnce(&mut Segment<K, V, B>) -> &mut V,
+    {
+        let guard = WriteGuard::new(RwLockWriteGuard::map(self.lock, f));
+        #[cfg(feature = "lock-stats")]
+        let guard = guard.with_held(self.held);
+        guard
+    }
+}
+
+impl<'a, K: 'a, V: 'a, B: 'a> Deref for SegmentWriteGuard<'a, K, V, B> {
+    type Target = Segment<K, V, B>;
+    fn deref(&self) -> &Segment<K, V, B> {
+        &self.lock
+    }
+}
+
+impl<'a, K: 'a, V: 'a, B: 'a> DerefMut for SegmentWriteGuard<'a, K, V, B> {
+    fn deref_mut(&mut self) -> &mut Segment<K, V, B> {
+        &mut self.lock
+    }
+}
+
 pub struct WriteGuard<'a, K: 'a, V: 'a, B: 'a> {
-    inner: OwningRefMut<RwLockWriteGuard<'a, HashMap<K, V, B>>, V>,
+    inner: ManuallyDrop<RwLockWriteGuard<'a, V>>,
+    pending: Option<PendingMutation<'a, K, V>>,
+    marker: PhantomData<&'a (K, B)>,
+    #[cfg(feature = "guard-timing")]
+    timer: guard_timing::HoldTimer,
+    #[cfg(feature = "entry-metadata")]
+    metadata: Option<EntryMetadata>,
+    #[cfg(feature = "lock-stats")]
+    held: Option<lock_stats::Held<'a>>,
+}
+
+impl<'a, K: 'a, V: 'a, B: 'a> WriteGuard<'a, K, V, B> {
+    fn new(inner: RwLockWriteGuard<'a, V>) -> Self {
+        WriteGuard {
+            inner: ManuallyDrop::new(inner),
+            pending: None,
+            marker: PhantomData,
+            #[cfg(feature = "guard-timing")]
+            timer: guard_timing::HoldTimer::start(),
+            #[cfg(feature = "entry-metadata")]
+            metadata: None,
+            #[cfg(feature = "lock-stats")]
+            held: None,
+        }
+    }
+
+    /// Moves the value out, leaving `V::default()` in the map.
+    pub fn take(&mut self) -> V
+    where
+        V: Default,
+    {
+        mem::take(&mut **self)
+    }
+
+    /// Stores `value` in the map and returns the one it replaced.
+    pub fn replace(&mut self, value: V) -> V {
+        mem::replace(&mut **self, value)
+    }
+
+    fn with_pending(mut self, pending: Option<PendingMutation<'a, K, V>>) -> Self {
+        self.pending = pending;
+        self
+    }
+
+    #[cfg(feature = "lock-stats")]
+    fn with_held(mut self, held: lock_stats::Held<'a>) -> Self {
+        self.held = Some(held);
+        self
+    }
+
+    #[cfg(feature = "entry-metadata")]
+    fn with_metadata(mut self, metadata: EntryMetadata) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    /// The entry's timestamps and access count as of this guard's acquisition.
+    #[cfg(feature = "entry-metadata")]
+    pub fn metadata(&self) -> EntryMetadata {
+        self.metadata.unwrap()
+    }
+}
+
+impl<'a, K: 'a, V: 'a, B: 'a> Drop for WriteGuard<'a, K, V, B> {
+    fn drop(&mut self) {
+        let pending = self.pending.take().map(|pending| {
+            let value = (pending.hook.clone_value)(&self.inner);
+            (pending, value)
+        });
+        // SAFETY: `inner` is not touched again after this; the lock has to be
+        // released before the observer runs, and before a long hold can panic.
+        unsafe { ManuallyDrop::drop(&mut self.inner) };
+        #[cfg(feature = "lock-stats")]
+        drop(self.held.take());
+        #[cfg(feature = "guard-timing")]
+        self.timer.finish("WriteGuard");
+        if let Some((pending, value)) = pending {
+            let mutation = if pending.created {
+                Mutation::Insert(pending.key, value)
+            } else {
+                Mutation::Update(pending.key, value)
+            };
+            pending.hook.notify(mutation);
+        }
+    }
 }
 
 impl<'a, K: 'a, V: 'a, B: 'a> Deref for WriteGuard<'a, K, V, B> {
@@ -231,22 +1508,23 @@ where
     type Item = (K, V);
     type IntoIter = ConcurrentHashMapIntoIter<K, V, B>;
     fn into_iter(self) -> Self::IntoIter {
-        let seg: fn(_) -> _ = |segment: RwLock<HashMap<K, V, B>>| segment.into_inner();
+        let seg: fn(_) -> _ =
+            |segment: RwLock<Segment<K, V, B>>| segment.into_inner().into_entries();
         let inner = self.segments.into_iter().flat_map(seg);
         ConcurrentHashMapIntoIter { inner }
     }
 }
 
-#[cfg_attr(feature = "cargo-clippy", allow(type_complexity))]
+#[allow(clippy::type_complexity)]
 pub struct ConcurrentHashMapIntoIter<K, V, B>
 where
     K: Eq + Hash,
     B: BuildHasher,
 {
     inner: FlatMap<
-        vec::IntoIter<RwLock<HashMap<K, V, B>>>,
-        HashMap<K, V, B>,
-        fn(RwLock<HashMap<K, V, B>>) -> HashMap<K, V, B>,
+        vec::IntoIter<RwLock<Segment<K, V, B>>>,
+        IntoEntries<K, V>,
+        fn(RwLock<Segment<K, V, B>>) -> IntoEntries<K, V>,
     >,
 }
 
@@ -261,6 +1539,35 @@ where
     }
 }
 
+pub struct Iter<'a, K: 'a, V: 'a, B: 'a> {
+    map: &'a ConcurrentHashMap<K, V, B>,
+    next_segment: usize,
+    current: vec::IntoIter<(K, V)>,
+}
+
+impl<'a, K: Clone + 'a, V: Clone + 'a, B: 'a> Iterator for Iter<'a, K, V, B> {
+    type Item = (K, V);
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.current.next() {
+                return Some(entry);
+            }
+            if self.next_segment == self.map.segments.len() {
+                return None;
+            }
+            let entries: Vec<_> = {
+                let segment = self.map.read_segment(self.next_segment);
+                segment
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect()
+            };
+            self.current = entries.into_iter();
+            self.next_segment += 1;
+        }
+    }
+}
+
 pub struct ConcurrentHashSet<K, B = RandomState> {
     table: ConcurrentHashMap<K, (), B>,
 }
@@ -296,23 +1603,74 @@ impl<K: Eq + Hash, B: BuildHasher + Default> ConcurrentHashSet<K, B> {
     }
 
     #[inline]
-    pub fn contains<Q: ?Sized>(&self, key: &Q) -> bool
+    pub fn contains<Q>(&self, key: &Q) -> bool
     where
         K: Borrow<Q>,
-        Q: Eq + Hash,
+        Q: ?Sized + Eq + Hash,
     {
         self.table.contains(key)
     }
 
     #[inline]
-    pub fn remove<Q: ?Sized>(&self, key: &Q) -> bool
+    pub fn remove<Q>(&self, key: &Q) -> bool
     where
         K: Borrow<Q>,
-        Q: Eq + Hash,
+        Q: ?Sized + Eq + Hash,
     {
         self.table.remove(key).is_some()
     }
 
+    /// Copies the elements out, one segment at a time; see `ConcurrentHashMap::iter`.
+    pub fn to_vec(&self) -> Vec<K>
+    where
+        K: Clone,
+    {
+        self.table.iter().map(|(key, ())| key).collect()
+    }
+
+    /// Picks up to `n` elements uniformly at random by reservoir sampling over a
+    /// live scan, so only the sample itself is held in memory.
+    #[cfg(feature = "rand")]
+    pub fn sample<R: rand::Rng>(&self, n: usize, rng: &mut R) -> Vec<K>
+    where
+        K: Clone,
+    {
+        let mut sample = Vec::with_capacity(n);
+        for (seen, (key, ())) in self.table.iter().enumerate() {
+            if sample.len() < n {
+                sample.push(key);
+            } else {
+                let slot = rng.gen_range(0, seen + 1);
+                if slot < n {
+                    sample[slot] = key;
+                }
+            }
+        }
+        sample
+    }
+
+    /// Returns the elements that are in exactly one of `self` and `other`. Each
+    /// side is scanned live, one segment at a time and without holding a lock on
+    /// the other set, so concurrent changes may or may not be reflected.
+    pub fn symmetric_difference_with<S>(&self, other: &ConcurrentHashSet<K, S>) -> Self
+    where
+        K: Clone,
+        S: BuildHasher + Default,
+    {
+        let difference = ConcurrentHashSet::default();
+        for (key, ()) in self.table.iter() {
+            if !other.contains(&key) {
+                difference.insert(key);
+            }
+        }
+        for (key, ()) in other.table.iter() {
+            if !self.contains(&key) {
+                difference.insert(key);
+            }
+        }
+        difference
+    }
+
     pub fn with_capacity_and_hasher(capacity: usize, hash_builder: B) -> Self {
         ConcurrentHashSet {
             table: ConcurrentHashMap::with_options(capacity, hash_builder, DEFAULT_SEGMENT_COUNT),
@@ -334,6 +1692,18 @@ impl<K: Eq + Hash, B: BuildHasher + Default> Default for ConcurrentHashSet<K, B>
     }
 }
 
+impl<K, B> Clone for ConcurrentHashSet<K, B>
+where
+    K: Eq + Hash + Clone,
+    B: BuildHasher + Default + Clone,
+{
+    fn clone(&self) -> Self {
+        ConcurrentHashSet {
+            table: self.table.clone(),
+        }
+    }
+}
+
 impl<K, B> Debug for ConcurrentHashSet<K, B>
 where
     K: Hash + Eq + Debug,
@@ -341,8 +1711,8 @@ where
 {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         write!(f, "ConcurrentHashSet{{")?;
-        for segment in &self.table.segments {
-            for key in segment.read().keys() {
+        for i in 0..self.table.segments.len() {
+            for key in self.table.read_segment(i).keys() {
                 write!(f, "{:?}, ", key)?;
             }
         }
@@ -0,0 +1,418 @@
+use parking_lot::{Condvar, Mutex};
+
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::{Clock, ConcurrentHashMap, Error, ReadGuard, SystemClock};
+
+/// A map that fills misses from a loader. Concurrent misses on the same key run
+/// the loader once; the other callers block until it finishes. The loader runs
+/// without any segment lock held, so it may itself use the cache.
+pub struct LoadingCache<K, V, F, B = RandomState> {
+    map: ConcurrentHashMap<K, V, B>,
+    loader: F,
+    pending: Mutex<HashMap<K, Arc<Latch>>>,
+    negatives: ConcurrentHashMap<K, Instant, B>,
+    negative_ttl: Option<Duration>,
+    // When each loaded value was written, and whether it has been read since it
+    // went stale. Only kept with a refresh period.
+    written: ConcurrentHashMap<K, (Instant, bool), B>,
+    refresh_after: Option<Duration>,
+    stats: StatsCounter,
+    clock: Box<dyn Clock>,
+}
+
+const LOAD_TIME_BUCKETS_MICROS: [u64; 7] = [10, 100, 1_000, 10_000, 100_000, 1_000_000, u64::MAX];
+
+/// A point-in-time copy of a cache's counters.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub load_successes: u64,
+    pub load_failures: u64,
+    pub expirations: u64,
+    pub total_load_time: Duration,
+    /// Load counts bucketed by duration, as `(upper bound, count)` pairs.
+    pub load_time_histogram: Vec<(Duration, u64)>,
+}
+
+#[derive(Default)]
+struct StatsCounter {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    load_successes: AtomicU64,
+    load_failures: AtomicU64,
+    expirations: AtomicU64,
+    total_load_nanos: AtomicU64,
+    load_time_buckets: [AtomicU64; 7],
+}
+
+impl StatsCounter {
+    fn record_load(&self, elapsed: Duration, success: bool) {
+        if success {
+            self.load_successes.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.load_failures.fetch_add(1, Ordering::Relaxed);
+        }
+        self.total_load_nanos
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+        let micros = elapsed.as_micros() as u64;
+        let bucket = LOAD_TIME_BUCKETS_MICROS
+            .iter()
+            .position(|&bound| micros < bound)
+            .unwrap_or(LOAD_TIME_BUCKETS_MICROS.len() - 1);
+        self.load_time_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            load_successes: self.load_successes.load(Ordering::Relaxed),
+            load_failures: self.load_failures.load(Ordering::Relaxed),
+            expirations: self.expirations.load(Ordering::Relaxed),
+            total_load_time: Duration::from_nanos(self.total_load_nanos.load(Ordering::Relaxed)),
+            load_time_histogram: LOAD_TIME_BUCKETS_MICROS
+                .iter()
+                .zip(self.load_time_buckets.iter())
+                .map(|(&bound, count)| {
+                    (Duration::from_micros(bound), count.load(Ordering::Relaxed))
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum LoadState {
+    Loading,
+    Loaded,
+    Absent,
+    Abandoned,
+}
+
+struct Latch {
+    state: Mutex<LoadState>,
+    cond: Condvar,
+}
+
+impl Latch {
+    fn wait(&self) -> LoadState {
+        let mut state = self.state.lock();
+        while *state == LoadState::Loading {
+            self.cond.wait(&mut state);
+        }
+        *state
+    }
+}
+
+// Removes the pending marker and wakes waiters even if the loader panics.
+struct LoadInProgress<'a, K: 'a + Eq + Hash> {
+    pending: &'a Mutex<HashMap<K, Arc<Latch>>>,
+    key: &'a K,
+    latch: Arc<Latch>,
+    outcome: LoadState,
+    stats: &'a StatsCounter,
+    started: Instant,
+}
+
+impl<'a, K: 'a + Eq + Hash> Drop for LoadInProgress<'a, K> {
+    fn drop(&mut self) {
+        self.stats
+            .record_load(self.started.elapsed(), self.outcome == LoadState::Loaded);
+        self.pending.lock().remove(self.key);
+        *self.latch.state.lock() = self.outcome;
+        self.latch.cond.notify_all();
+    }
+}
+
+impl<K: Eq + Hash + Clone, V, F: Fn(&K) -> V> LoadingCache<K, V, F, RandomState> {
+    pub fn new(loader: F) -> Self {
+        LoadingCache::with_map(ConcurrentHashMap::new(), loader)
+    }
+}
+
+impl<K, V, F, B> LoadingCache<K, V, F, B>
+where
+    K: Eq + Hash + Clone,
+    F: Fn(&K) -> V,
+    B: BuildHasher + Default,
+{
+    pub fn with_map(map: ConcurrentHashMap<K, V, B>, loader: F) -> Self {
+        LoadingCache {
+            map,
+            loader,
+            pending: Mutex::new(HashMap::new()),
+            negatives: ConcurrentHashMap::default(),
+            negative_ttl: None,
+            written: ConcurrentHashMap::default(),
+            refresh_after: None,
+            stats: StatsCounter::default(),
+            clock: Box::new(SystemClock),
+        }
+    }
+
+    /// Remembers `None` results from `try_get_with` for `ttl`, during which lookups
+    /// of that key return `None` without calling a loader.
+    pub fn with_negative_ttl(mut self, ttl: Duration) -> Self {
+        self.negative_ttl = Some(ttl);
+        self
+    }
+
+    /// Entries loaded more than `period` ago are still returned straight away,
+    /// but a read marks them for reload. `refresh_stale` reloads the marked
+    /// entries through the registered loader; a `MaintenanceHandle` runs it in
+    /// the background.
+    pub fn with_refresh_after_write(mut self, period: Duration) -> Self {
+        self.refresh_after = Some(period);
+        self
+    }
+
+    /// Uses `clock` instead of the system clock for negative TTL and refresh.
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Fails with `Error::Closed`, without loading, if the cache has been closed
+    /// and `key` is not present.
+    pub fn get(&self, key: &K) -> Result<ReadGuard<'_, K, V, B>, Error> {
+        self.get_with(key, &self.loader)
+    }
+
+    /// Like `get`, but fills a miss with `loader` instead of the configured one.
+    pub fn get_with<G>(&self, key: &K, loader: G) -> Result<ReadGuard<'_, K, V, B>, Error>
+    where
+        G: Fn(&K) -> V,
+    {
+        loop {
+            if let Some(guard) = self.load(key, |k| Some(loader(k)), false)? {
+                return Ok(guard);
+            }
+        }
+    }
+
+    /// Fills a miss with a loader that may find nothing. A `None` result is not
+    /// stored in the map, but is remembered if a negative TTL is configured.
+    pub fn try_get_with<G>(
+        &self,
+        key: &K,
+        loader: G,
+    ) -> Result<Option<ReadGuard<'_, K, V, B>>, Error>
+    where
+        G: Fn(&K) -> Option<V>,
+    {
+        self.load(key, loader, true)
+    }
+
+    // The infallible path ignores negative entries, so `get` still loads a key
+    // that `try_get_with` has cached as absent.
+    fn load<G>(
+        &self,
+        key: &K,
+        loader: G,
+        honor_negative: bool,
+    ) -> Result<Option<ReadGuard<'_, K, V, B>>, Error>
+    where
+        G: Fn(&K) -> Option<V>,
+    {
+        let mut missed = false;
+        loop {
+            if let Some(guard) = self.map.get(key) {
+                if !missed {
+                    self.stats.hits.fetch_add(1, Ordering::Relaxed);
+                }
+                self.note_read(key);
+                return Ok(Some(guard));
+            }
+            if honor_negative && self.is_known_absent(key) {
+                if !missed {
+                    self.stats.hits.fetch_add(1, Ordering::Relaxed);
+                }
+                return Ok(None);
+            }
+            if !missed {
+                missed = true;
+                self.stats.misses.fetch_add(1, Ordering::Relaxed);
+            }
+            if self.map.is_closed() {
+                return Err(Error::Closed);
+            }
+            let latch = {
+                let mut pending = self.pending.lock();
+                if let Some(latch) = pending.get(key) {
+                    latch.clone()
+                } else if self.map.contains(key) {
+                    continue;
+                } else {
+                    let latch = Arc::new(Latch {
+                        state: Mutex::new(LoadState::Loading),
+                        cond: Condvar::new(),
+                    });
+                    pending.insert(key.clone(), latch.clone());
+                    drop(pending);
+                    let mut in_progress = LoadInProgress {
+                        pending: &self.pending,
+                        key,
+                        latch,
+                        outcome: LoadState::Abandoned,
+                        stats: &self.stats,
+                        started: Instant::now(),
+                    };
+                    match loader(key) {
+                        Some(value) => {
+                            self.negatives.remove(key);
+                            // The map may have been closed while loading; the
+                            // latch is released and any waiters see the error.
+                            self.map
+                                .try_insert(key.clone(), value)
+                                .map_err(|rejected| rejected.error)?;
+                            self.note_written(key);
+                            in_progress.outcome = LoadState::Loaded;
+                        }
+                        None => {
+                            if let Some(ttl) = self.negative_ttl {
+                                self.negatives.insert(key.clone(), self.clock.now() + ttl);
+                            }
+                            in_progress.outcome = LoadState::Absent;
+                            return Ok(None);
+                        }
+                    }
+                    continue;
+                }
+            };
+            if latch.wait() == LoadState::Absent {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn note_written(&self, key: &K) {
+        if self.refresh_after.is_some() {
+            self.written.insert(key.clone(), (self.clock.now(), false));
+        }
+    }
+
+    fn note_read(&self, key: &K) {
+        let period = match self.refresh_after {
+            Some(period) => period,
+            None => return,
+        };
+        let stale = match self.written.get(key) {
+            Some(written) => !written.1 && written.0 + period <= self.clock.now(),
+            None => false,
+        };
+        if stale {
+            if let Some(mut written) = self.written.get_mut(key) {
+                written.1 = true;
+            }
+        }
+    }
+
+    /// Reloads every entry that was read after its refresh period had passed,
+    /// and returns how many were reloaded. Readers keep getting the old value
+    /// until its replacement is stored. Entries already being loaded are skipped.
+    pub fn refresh_stale(&self) -> usize {
+        let due: Vec<K> = self
+            .written
+            .iter()
+            .filter(|&(_, (_, read_while_stale))| read_while_stale)
+            .map(|(key, _)| key)
+            .collect();
+        let mut refreshed = 0;
+        for key in &due {
+            let latch = {
+                let mut pending = self.pending.lock();
+                if pending.contains_key(key) || !self.map.contains(key) {
+                    continue;
+                }
+                let latch = Arc::new(Latch {
+                    state: Mutex::new(LoadState::Loading),
+                    cond: Condvar::new(),
+                });
+                pending.insert(key.clone(), latch.clone());
+                latch
+            };
+            let mut in_progress = LoadInProgress {
+                pending: &self.pending,
+                key,
+                latch,
+                outcome: LoadState::Abandoned,
+                stats: &self.stats,
+                started: Instant::now(),
+            };
+            let value = (self.loader)(key);
+            if self.map.try_insert(key.clone(), value).is_err() {
+                break;
+            }
+            self.note_written(key);
+            in_progress.outcome = LoadState::Loaded;
+            refreshed += 1;
+        }
+        refreshed
+    }
+
+    fn is_known_absent(&self, key: &K) -> bool {
+        let expired = match self.negatives.get(key) {
+            None => return false,
+            Some(expires_at) => *expires_at <= self.clock.now(),
+        };
+        if expired && self.negatives.remove(key).is_some() {
+            self.stats.expirations.fetch_add(1, Ordering::Relaxed);
+        }
+        !expired
+    }
+
+    /// Drops every negative entry whose TTL has passed and returns how many were
+    /// removed. Expired entries are otherwise only cleared when looked up.
+    pub fn expire_now(&self) -> usize {
+        let now = self.clock.now();
+        let expired = self
+            .negatives
+            .remove_where(|_, expires_at| *expires_at <= now);
+        self.stats
+            .expirations
+            .fetch_add(expired as u64, Ordering::Relaxed);
+        expired
+    }
+
+    pub fn get_if_present<Q>(&self, key: &Q) -> Option<ReadGuard<'_, K, V, B>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.map.get(key)
+    }
+
+    pub fn invalidate<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.negatives.remove(key);
+        self.written.remove(key);
+        self.map.remove(key)
+    }
+
+    /// Closes the underlying map and returns its entries; see
+    /// `ConcurrentHashMap::close`. Later misses fail with `Error::Closed`.
+    pub fn close(&self) -> Vec<(K, V)> {
+        self.negatives.close();
+        self.written.close();
+        self.map.close()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats.snapshot()
+    }
+
+    pub fn map(&self) -> &ConcurrentHashMap<K, V, B> {
+        &self.map
+    }
+}
@@ -0,0 +1,99 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+const HOLD_TIME_BUCKETS_MICROS: [u64; 7] = [1, 10, 100, 1_000, 10_000, 100_000, u64::MAX];
+
+/// A point-in-time copy of one segment's lock counters. All but the read wait
+/// counters are for the write lock.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SegmentLockStats {
+    pub acquisitions: u64,
+    /// Acquisitions that found the lock already held.
+    pub contended: u64,
+    /// Read acquisitions that had to wait for a writer.
+    pub contended_reads: u64,
+    /// Time spent blocked waiting for the lock, by readers and writers. Waits
+    /// bounded by a timeout are not included.
+    pub total_wait_time: Duration,
+    pub total_hold_time: Duration,
+    /// Acquisitions bucketed by how long the lock was held, as `(upper bound, count)`
+    /// pairs.
+    pub hold_time_histogram: Vec<(Duration, u64)>,
+}
+
+#[derive(Default)]
+pub(crate) struct LockCounters {
+    acquisitions: AtomicU64,
+    contended: AtomicU64,
+    contended_reads: AtomicU64,
+    total_wait_nanos: AtomicU64,
+    total_hold_nanos: AtomicU64,
+    hold_time_buckets: [AtomicU64; 7],
+}
+
+impl LockCounters {
+    pub(crate) fn contended(&self) {
+        self.contended.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn contended_read(&self) {
+        self.contended_reads.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Runs a blocking acquisition and counts the time it took as waiting.
+    pub(crate) fn wait<T, F: FnOnce() -> T>(&self, acquire: F) -> T {
+        let started = Instant::now();
+        let lock = acquire();
+        self.total_wait_nanos
+            .fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        lock
+    }
+
+    pub(crate) fn acquired(&self) -> Held<'_> {
+        self.acquisitions.fetch_add(1, Ordering::Relaxed);
+        Held {
+            counters: self,
+            acquired: Instant::now(),
+        }
+    }
+
+    fn record_hold(&self, held: Duration) {
+        self.total_hold_nanos
+            .fetch_add(held.as_nanos() as u64, Ordering::Relaxed);
+        let micros = held.as_micros() as u64;
+        let bucket = HOLD_TIME_BUCKETS_MICROS
+            .iter()
+            .position(|&bound| micros < bound)
+            .unwrap_or(HOLD_TIME_BUCKETS_MICROS.len() - 1);
+        self.hold_time_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> SegmentLockStats {
+        SegmentLockStats {
+            acquisitions: self.acquisitions.load(Ordering::Relaxed),
+            contended: self.contended.load(Ordering::Relaxed),
+            contended_reads: self.contended_reads.load(Ordering::Relaxed),
+            total_wait_time: Duration::from_nanos(self.total_wait_nanos.load(Ordering::Relaxed)),
+            total_hold_time: Duration::from_nanos(self.total_hold_nanos.load(Ordering::Relaxed)),
+            hold_time_histogram: HOLD_TIME_BUCKETS_MICROS
+                .iter()
+                .zip(self.hold_time_buckets.iter())
+                .map(|(&bound, count)| {
+                    (Duration::from_micros(bound), count.load(Ordering::Relaxed))
+                })
+                .collect(),
+        }
+    }
+}
+
+// Records the hold time when dropped, which must be right after the lock is released.
+pub(crate) struct Held<'a> {
+    counters: &'a LockCounters,
+    acquired: Instant,
+}
+
+impl<'a> Drop for Held<'a> {
+    fn drop(&mut self) {
+        self.counters.record_hold(self.acquired.elapsed());
+    }
+}
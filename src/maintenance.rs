@@ -0,0 +1,93 @@
+use parking_lot::{Condvar, Mutex};
+
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use super::{ConcurrentHashMap, LoadingCache};
+
+/// Periodic housekeeping that a `MaintenanceHandle` can drive.
+pub trait Maintain: Send + Sync {
+    fn run_maintenance(&self);
+}
+
+impl<K, V, B> Maintain for ConcurrentHashMap<K, V, B>
+where
+    K: Eq + Hash + Send + Sync,
+    V: Send + Sync,
+    B: BuildHasher + Default + Send + Sync,
+{
+    fn run_maintenance(&self) {
+        self.shrink_to_fit();
+    }
+}
+
+impl<K, V, F, B> Maintain for LoadingCache<K, V, F, B>
+where
+    K: Eq + Hash + Clone + Send + Sync,
+    V: Send + Sync,
+    F: Fn(&K) -> V + Send + Sync,
+    B: BuildHasher + Default + Send + Sync,
+{
+    fn run_maintenance(&self) {
+        self.expire_now();
+        self.refresh_stale();
+        self.map().shrink_to_fit();
+    }
+}
+
+/// A background thread that runs a target's maintenance every `interval`.
+/// Dropping the handle stops the thread and waits for it to exit.
+pub struct MaintenanceHandle {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MaintenanceHandle {
+    pub fn start<T: Maintain + 'static>(target: Arc<T>, interval: Duration) -> Self {
+        MaintenanceHandle::start_with(target, interval, |_| {})
+    }
+
+    /// Like `start`, but calls `after_each` with the target after every pass,
+    /// e.g. to collect its stats.
+    pub fn start_with<T, F>(target: Arc<T>, interval: Duration, mut after_each: F) -> Self
+    where
+        T: Maintain + 'static,
+        F: FnMut(&T) + Send + 'static,
+    {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let signal = stop.clone();
+        let thread = thread::spawn(move || {
+            let (ref stopped, ref cond) = *signal;
+            let mut stopped = stopped.lock();
+            loop {
+                cond.wait_for(&mut stopped, interval);
+                if *stopped {
+                    return;
+                }
+                target.run_maintenance();
+                after_each(&target);
+            }
+        });
+        MaintenanceHandle {
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    /// Stops the thread, waiting for an in-progress pass to finish.
+    pub fn stop(self) {
+        drop(self);
+    }
+}
+
+impl Drop for MaintenanceHandle {
+    fn drop(&mut self) {
+        *self.stop.0.lock() = true;
+        self.stop.1.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EntryMetadata {
+    pub created: Instant,
+    pub last_accessed: Instant,
+    /// Number of `get`/`get_mut` calls that found the entry, including this one
+    /// when read through a guard.
+    pub access_count: u64,
+}
+
+// Kept in each entry's slot. Accesses only need the segment's read lock, hence
+// the atomics.
+pub(crate) struct EntryMeta {
+    created: Instant,
+    last_access_nanos: AtomicU64,
+    accesses: AtomicU64,
+}
+
+impl EntryMeta {
+    pub(crate) fn new(created: Instant) -> Self {
+        EntryMeta {
+            created,
+            last_access_nanos: AtomicU64::new(0),
+            accesses: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn accessed(&self, now: Instant) -> EntryMetadata {
+        let since_created = now.saturating_duration_since(self.created).as_nanos() as u64;
+        self.last_access_nanos
+            .fetch_max(since_created, Ordering::Relaxed);
+        self.accesses.fetch_add(1, Ordering::Relaxed);
+        self.snapshot()
+    }
+
+    pub(crate) fn snapshot(&self) -> EntryMetadata {
+        EntryMetadata {
+            created: self.created,
+            last_accessed: self.created
+                + Duration::from_nanos(self.last_access_nanos.load(Ordering::Relaxed)),
+            access_count: self.accesses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Clone for EntryMeta {
+    fn clone(&self) -> Self {
+        EntryMeta {
+            created: self.created,
+            last_access_nanos: AtomicU64::new(self.last_access_nanos.load(Ordering::Relaxed)),
+            accesses: AtomicU64::new(self.accesses.load(Ordering::Relaxed)),
+        }
+    }
+}
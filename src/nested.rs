@@ -0,0 +1,130 @@
+use std::borrow::Borrow;
+use std::hash::{BuildHasher, Hash};
+
+use super::{ConcurrentHashMap, Error, Mutation};
+
+// Inner maps are only created and written while holding the outer segment's
+// lock, and removed only under its write lock after re-checking that they are
+// empty, so an insert can never land in an inner map that is being discarded.
+//
+// The outer map's observer sees a write to an inner map as an insert or update
+// of the whole inner map, so every nested write hands it a copy of that map.
+impl<K1, K2, V, B1, B2> ConcurrentHashMap<K1, ConcurrentHashMap<K2, V, B2>, B1>
+where
+    K1: Eq + Hash,
+    K2: Eq + Hash,
+    B1: BuildHasher + Default,
+    B2: BuildHasher + Default,
+{
+    /// Inserts `value` under `inner` in the map under `outer`, creating that
+    /// map if needed. Fails with `Error::Closed` if the outer map has been
+    /// closed.
+    pub fn insert_nested(&self, outer: K1, inner: K2, value: V) -> Result<Option<V>, Error> {
+        let hash = self.hash(&outer);
+        let segment_index = self.get_segment(hash);
+        let hook = self.observer.as_ref();
+        let (previous, mutation) = {
+            let segment = self.read_segment(segment_index);
+            if self.is_closed() {
+                return Err(Error::Closed);
+            }
+            match segment.get_key_value(&outer) {
+                Some((k, map)) => {
+                    let previous = map.insert(inner, value);
+                    let mutation = hook.map(|hook| {
+                        let (k, v) = hook.capture(k, map);
+                        Mutation::Update(k, v)
+                    });
+                    (previous, mutation)
+                }
+                None => {
+                    drop(segment);
+                    let mut segment = self.write_segment(segment_index);
+                    if self.is_closed() {
+                        return Err(Error::Closed);
+                    }
+                    let created = !segment.contains_key(&outer);
+                    let entry = segment.table_mut().entry(outer);
+                    let k = hook.map(|hook| (hook.clone_key)(entry.key()));
+                    let map = &mut entry.or_insert_with(|| self.slot(Default::default())).value;
+                    let previous = map.insert(inner, value);
+                    let mutation = hook.map(|hook| {
+                        let (k, v) = (k.unwrap(), (hook.clone_value)(map));
+                        if created {
+                            Mutation::Insert(k, v)
+                        } else {
+                            Mutation::Update(k, v)
+                        }
+                    });
+                    (previous, mutation)
+                }
+            }
+        };
+        self.notify(mutation);
+        Ok(previous)
+    }
+
+    pub fn get_nested<Q1, Q2>(&self, outer: &Q1, inner: &Q2) -> Option<V>
+    where
+        K1: Borrow<Q1>,
+        K2: Borrow<Q2>,
+        Q1: ?Sized + Eq + Hash,
+        Q2: ?Sized + Eq + Hash,
+        V: Clone,
+    {
+        let segment = self.read_segment(self.get_segment(self.hash(outer)));
+        let map = segment.get(outer)?;
+        let value = map.get(inner).map(|v| v.clone());
+        value
+    }
+
+    /// Removes `inner` from the map under `outer`, and drops that map if it is
+    /// left empty.
+    pub fn remove_nested<Q1, Q2>(&self, outer: &Q1, inner: &Q2) -> Option<V>
+    where
+        K1: Borrow<Q1>,
+        K2: Borrow<Q2>,
+        Q1: ?Sized + Eq + Hash,
+        Q2: ?Sized + Eq + Hash,
+    {
+        let hash = self.hash(outer);
+        let segment_index = self.get_segment(hash);
+        let hook = self.observer.as_ref();
+        let removed = {
+            let segment = self.read_segment(segment_index);
+            let (k, map) = segment.get_key_value(outer)?;
+            let removed = map.remove(inner);
+            if !map.is_empty() {
+                let mutation = match removed {
+                    Some(_) => hook.map(|hook| {
+                        let (k, v) = hook.capture(k, map);
+                        Mutation::Update(k, v)
+                    }),
+                    None => None,
+                };
+                drop(segment);
+                self.notify(mutation);
+                return removed;
+            }
+            removed
+        };
+        let mut segment = self.write_segment(segment_index);
+        let now_empty = segment.get(outer).is_some_and(|map| map.is_empty());
+        if now_empty && !segment.is_pinned(outer) {
+            let (k, map) = segment.remove_entry(outer).unwrap();
+            drop(segment);
+            if let Some(hook) = hook {
+                hook.observer.on_remove(&k, &map);
+            }
+        } else if removed.is_some() {
+            let mutation = hook.and_then(|hook| {
+                let (k, map) = segment.get_key_value(outer)?;
+                let (k, v) = hook.capture(k, map);
+                Some(Mutation::Update(k, v))
+            });
+            drop(segment);
+            self.notify(mutation);
+        }
+        removed
+    }
+}
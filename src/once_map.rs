@@ -0,0 +1,92 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+use super::ConcurrentHashMap;
+
+/// An append-only map whose values are never moved or removed once inserted,
+/// so lookups return plain references that live as long as the map.
+pub struct OnceMap<K, V, B = RandomState> {
+    inner: ConcurrentHashMap<K, Box<V>, B>,
+}
+
+impl<K: Eq + Hash, V> OnceMap<K, V, RandomState> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<K: Eq + Hash, V, B: BuildHasher + Default> OnceMap<K, V, B> {
+    pub fn with_options(capacity: usize, hash_builder: B, concurrency_level: usize) -> Self {
+        OnceMap {
+            inner: ConcurrentHashMap::with_options(capacity, hash_builder, concurrency_level),
+        }
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        let segment = self.inner.read_segment(self.inner.segment_index(key));
+        // SAFETY: the value lives in its own box, which stays at a fixed address
+        // after the read lock is released: entries are never removed or
+        // replaced, and `inner` is private, so nothing outside this type can.
+        segment.get(key).map(|boxed| unsafe { extend(&**boxed) })
+    }
+
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.inner.contains(key)
+    }
+
+    /// Inserts `value` unless `key` is already present, and returns the value
+    /// stored for `key` either way.
+    pub fn insert(&self, key: K, value: V) -> &V {
+        let mut segment = self
+            .inner
+            .write_segment(self.inner.get_segment(self.inner.hash(&key)));
+        let boxed = segment
+            .table_mut()
+            .entry(key)
+            .or_insert_with(|| self.inner.slot(Box::new(value)));
+        // SAFETY: the box, whether just inserted or already present, stays at a
+        // fixed address after the write lock is released: `or_insert_with` never
+        // overwrites an entry, entries are never removed, and `inner` is private.
+        unsafe { extend(&*boxed.value) }
+    }
+
+    /// Returns the value for `key`, computing it with `f` if absent. `f` runs
+    /// without any lock held; if another thread inserts the key first, its value
+    /// wins and the computed one is dropped.
+    pub fn get_or_insert_with<F>(&self, key: K, f: F) -> &V
+    where
+        F: FnOnce() -> V,
+    {
+        if let Some(value) = self.get(&key) {
+            return value;
+        }
+        self.insert(key, f())
+    }
+}
+
+// Safety: a value's box is never dropped, replaced or moved out while the map is
+// shared: only `insert` mutates through `&self`, and it never overwrites an
+// existing entry. The inner map is private, so its segments are never shared
+// through `cow_clone`, which would copy the boxes. The returned reference is
+// bound to `&self`, so the map cannot be dropped or mutably borrowed while it
+// is alive.
+unsafe fn extend<'a, V>(value: &V) -> &'a V {
+    &*(value as *const V)
+}
+
+impl<K: Eq + Hash, V, B: BuildHasher + Default> Default for OnceMap<K, V, B> {
+    fn default() -> Self {
+        OnceMap {
+            inner: ConcurrentHashMap::default(),
+        }
+    }
+}
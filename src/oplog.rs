@@ -0,0 +1,91 @@
+use parking_lot::Mutex;
+
+use std::collections::VecDeque;
+
+use super::Observer;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Operation<K, V> {
+    Insert(K, V),
+    Remove(K),
+}
+
+/// A bounded, sequence-numbered record of the mutations made to a map.
+///
+/// Install it on a primary map with `with_observer`, publish `ops_since` to
+/// replicas and feed them to `ConcurrentHashMap::apply`. Hooks for
+/// concurrent writes to the same key may be recorded in a different order than
+/// they were applied, so replicas are only kept approximately in sync.
+pub struct OpLog<K, V> {
+    inner: Mutex<LogInner<K, V>>,
+    capacity: usize,
+}
+
+struct LogInner<K, V> {
+    ops: VecDeque<(u64, Operation<K, V>)>,
+    next_sequence: u64,
+}
+
+impl<K: Clone, V: Clone> OpLog<K, V> {
+    pub fn with_capacity(capacity: usize) -> Self {
+        OpLog {
+            inner: Mutex::new(LogInner {
+                ops: VecDeque::with_capacity(capacity),
+                next_sequence: 0,
+            }),
+            capacity,
+        }
+    }
+
+    /// Returns every retained operation with a sequence number of at least `sequence`.
+    pub fn ops_since(&self, sequence: u64) -> Vec<(u64, Operation<K, V>)> {
+        let inner = self.inner.lock();
+        inner
+            .ops
+            .iter()
+            .filter(|&&(seq, _)| seq >= sequence)
+            .cloned()
+            .collect()
+    }
+
+    /// The oldest sequence number still retained, if any. A replica that has not
+    /// seen this sequence number has fallen too far behind to catch up from the log.
+    pub fn first_sequence(&self) -> Option<u64> {
+        self.inner.lock().ops.front().map(|&(seq, _)| seq)
+    }
+
+    pub fn next_sequence(&self) -> u64 {
+        self.inner.lock().next_sequence
+    }
+
+    fn record(&self, op: Operation<K, V>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut inner = self.inner.lock();
+        if inner.ops.len() == self.capacity {
+            inner.ops.pop_front();
+        }
+        let seq = inner.next_sequence;
+        inner.next_sequence += 1;
+        inner.ops.push_back((seq, op));
+    }
+}
+
+impl<K, V> Observer<K, V> for OpLog<K, V>
+where
+    K: Clone + Send,
+    V: Clone + Send,
+{
+    fn on_insert(&self, key: &K, value: &V) {
+        self.record(Operation::Insert(key.clone(), value.clone()));
+    }
+
+    fn on_update(&self, key: &K, value: &V) {
+        self.record(Operation::Insert(key.clone(), value.clone()));
+    }
+
+    fn on_remove(&self, key: &K, _value: &V) {
+        self.record(Operation::Remove(key.clone()));
+    }
+}
@@ -0,0 +1,82 @@
+use rayon::prelude::*;
+
+use std::cmp::Ordering;
+use std::hash::{BuildHasher, Hash};
+use std::iter::Sum;
+
+use super::query::pick_max;
+use super::ConcurrentHashMap;
+
+// Parallel counterparts of the scans in `query`, visiting segments on the rayon
+// thread pool. The same per-segment consistency applies.
+impl<K, V, B> ConcurrentHashMap<K, V, B>
+where
+    K: Eq + Hash + Send + Sync,
+    V: Send + Sync,
+    B: BuildHasher + Default + Send + Sync,
+{
+    pub fn par_count_if<F>(&self, pred: F) -> usize
+    where
+        F: Fn(&K, &V) -> bool + Sync,
+    {
+        (0..self.segments.len())
+            .into_par_iter()
+            .map(|i| {
+                self.read_segment(i)
+                    .iter()
+                    .filter(|&(k, v)| pred(k, v))
+                    .count()
+            })
+            .sum()
+    }
+
+    pub fn par_max_by<F>(&self, cmp: F) -> Option<(K, V)>
+    where
+        K: Clone,
+        V: Clone,
+        F: Fn((&K, &V), (&K, &V)) -> Ordering + Sync,
+    {
+        (0..self.segments.len())
+            .into_par_iter()
+            .filter_map(|i| self.segment_max_by(i, &mut |a, b| cmp(a, b)))
+            .reduce_with(|best, candidate| pick_max(Some(best), candidate, &mut |a, b| cmp(a, b)))
+    }
+
+    pub fn par_min_by<F>(&self, cmp: F) -> Option<(K, V)>
+    where
+        K: Clone,
+        V: Clone,
+        F: Fn((&K, &V), (&K, &V)) -> Ordering + Sync,
+    {
+        self.par_max_by(|a, b| cmp(b, a))
+    }
+
+    /// Folds each segment under its read lock starting from `init()`, then
+    /// merges the per-segment results with `combine`.
+    pub fn aggregate<A, I, F, C>(&self, init: I, fold: F, combine: C) -> A
+    where
+        A: Send,
+        I: Fn() -> A + Sync,
+        F: Fn(A, &K, &V) -> A + Sync,
+        C: Fn(A, A) -> A + Sync,
+    {
+        (0..self.segments.len())
+            .into_par_iter()
+            .map(|i| {
+                self.read_segment(i)
+                    .iter()
+                    .fold(init(), |acc, (k, v)| fold(acc, k, v))
+            })
+            .reduce(&init, &combine)
+    }
+
+    pub fn sum_values(&self) -> V
+    where
+        V: Sum + for<'v> Sum<&'v V>,
+    {
+        (0..self.segments.len())
+            .into_par_iter()
+            .map(|i| self.read_segment(i).values().sum::<V>())
+            .sum()
+    }
+}
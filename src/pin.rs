@@ -0,0 +1,42 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// Each pinned entry's count lives in its slot, and is taken and checked while
+// holding the entry's segment lock, which orders pins against removals. The
+// map-wide total lets unpinned maps skip the per-entry checks entirely.
+pub(crate) struct Pins {
+    pinned: AtomicUsize,
+}
+
+impl Pins {
+    pub(crate) fn new() -> Self {
+        Pins {
+            pinned: AtomicUsize::new(0),
+        }
+    }
+
+    // `count` has already been incremented by the segment.
+    pub(crate) fn pin(&self, count: Arc<AtomicUsize>) -> PinGuard<'_> {
+        self.pinned.fetch_add(1, Ordering::Relaxed);
+        PinGuard { pins: self, count }
+    }
+
+    #[inline]
+    pub(crate) fn any(&self) -> bool {
+        self.pinned.load(Ordering::Relaxed) != 0
+    }
+}
+
+/// Keeps an entry in its map until dropped: `remove` and the bulk removal
+/// operations leave pinned entries in place. Dropping the guard takes no lock.
+pub struct PinGuard<'a> {
+    pins: &'a Pins,
+    count: Arc<AtomicUsize>,
+}
+
+impl<'a> Drop for PinGuard<'a> {
+    fn drop(&mut self) {
+        self.count.fetch_sub(1, Ordering::Relaxed);
+        self.pins.pinned.fetch_sub(1, Ordering::Relaxed);
+    }
+}
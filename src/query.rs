@@ -0,0 +1,161 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::hash::{BuildHasher, Hash};
+
+use super::ConcurrentHashMap;
+
+// Read-only scans over the live map. Each segment is read under its own lock,
+// so results reflect each segment at the moment it was visited rather than a
+// single point in time.
+impl<K, V, B> ConcurrentHashMap<K, V, B>
+where
+    K: Eq + Hash,
+    B: BuildHasher + Default,
+{
+    pub fn count_if<F>(&self, mut pred: F) -> usize
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        (0..self.segments.len())
+            .map(|i| {
+                self.read_segment(i)
+                    .iter()
+                    .filter(|&(k, v)| pred(k, v))
+                    .count()
+            })
+            .sum()
+    }
+
+    /// Stops at the first matching entry, without visiting later segments.
+    pub fn any<F>(&self, mut pred: F) -> bool
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        (0..self.segments.len()).any(|i| self.read_segment(i).iter().any(|(k, v)| pred(k, v)))
+    }
+
+    /// Stops at the first entry that does not match. True for an empty map.
+    pub fn all<F>(&self, mut pred: F) -> bool
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        (0..self.segments.len()).all(|i| self.read_segment(i).iter().all(|(k, v)| pred(k, v)))
+    }
+
+    /// Clones out the greatest entry according to `cmp`. Only each segment's
+    /// best entry is cloned, after which that segment's lock is released.
+    pub fn max_by<F>(&self, mut cmp: F) -> Option<(K, V)>
+    where
+        K: Clone,
+        V: Clone,
+        F: FnMut((&K, &V), (&K, &V)) -> Ordering,
+    {
+        let mut best = None;
+        for i in 0..self.segments.len() {
+            if let Some(candidate) = self.segment_max_by(i, &mut cmp) {
+                best = Some(pick_max(best, candidate, &mut cmp));
+            }
+        }
+        best
+    }
+
+    pub fn min_by<F>(&self, mut cmp: F) -> Option<(K, V)>
+    where
+        K: Clone,
+        V: Clone,
+        F: FnMut((&K, &V), (&K, &V)) -> Ordering,
+    {
+        self.max_by(|a, b| cmp(b, a))
+    }
+
+    /// The `k` entries with the highest `score`, best first. Each segment keeps
+    /// only its own top `k` while locked, so at most `k` clones are made per
+    /// segment.
+    pub fn top_k<S, F>(&self, k: usize, mut score: F) -> Vec<(K, V)>
+    where
+        K: Clone,
+        V: Clone,
+        S: Ord,
+        F: FnMut(&K, &V) -> S,
+    {
+        if k == 0 {
+            return Vec::new();
+        }
+        let mut best = BinaryHeap::with_capacity(k + 1);
+        for i in 0..self.segments.len() {
+            let segment = self.read_segment(i);
+            let mut local = BinaryHeap::with_capacity(k + 1);
+            for (key, value) in segment.iter() {
+                push_bounded(&mut local, k, score(key, value), (key, value));
+            }
+            for MinScored(s, (key, value)) in local {
+                push_bounded(&mut best, k, s, (key.clone(), value.clone()));
+            }
+        }
+        best.into_sorted_vec()
+            .into_iter()
+            .map(|MinScored(_, entry)| entry)
+            .collect()
+    }
+
+    pub(crate) fn segment_max_by<F>(&self, index: usize, cmp: &mut F) -> Option<(K, V)>
+    where
+        K: Clone,
+        V: Clone,
+        F: FnMut((&K, &V), (&K, &V)) -> Ordering,
+    {
+        self.read_segment(index)
+            .iter()
+            .max_by(|&a, &b| cmp(a, b))
+            .map(|(k, v)| (k.clone(), v.clone()))
+    }
+}
+
+pub(crate) fn pick_max<K, V, F>(best: Option<(K, V)>, candidate: (K, V), cmp: &mut F) -> (K, V)
+where
+    F: FnMut((&K, &V), (&K, &V)) -> Ordering,
+{
+    match best {
+        Some(best) => {
+            if cmp((&candidate.0, &candidate.1), (&best.0, &best.1)) == Ordering::Greater {
+                candidate
+            } else {
+                best
+            }
+        }
+        None => candidate,
+    }
+}
+
+// Orders by score alone, reversed, so a `BinaryHeap` of these keeps the lowest
+// score on top and can be trimmed to the best `k`.
+struct MinScored<S, T>(S, T);
+
+impl<S: Ord, T> PartialEq for MinScored<S, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<S: Ord, T> Eq for MinScored<S, T> {}
+
+impl<S: Ord, T> PartialOrd for MinScored<S, T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<S: Ord, T> Ord for MinScored<S, T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.0.cmp(&self.0)
+    }
+}
+
+fn push_bounded<S: Ord, T>(heap: &mut BinaryHeap<MinScored<S, T>>, k: usize, score: S, item: T) {
+    if heap.len() < k {
+        heap.push(MinScored(score, item));
+    } else if heap.peek().is_some_and(|lowest| score > lowest.0) {
+        heap.pop();
+        heap.push(MinScored(score, item));
+    }
+}
@@ -0,0 +1,103 @@
+use std::hash::{BuildHasher, Hash};
+
+use super::ConcurrentHashMap;
+
+/// Where a `scan` left off. Within a segment, entries are visited in order of
+/// their hash, which unlike their position in the table does not change as the
+/// map grows or shrinks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Cursor {
+    segment: usize,
+    from_hash: u64,
+}
+
+impl Cursor {
+    /// A cursor for the first page.
+    pub fn start() -> Self {
+        Cursor::default()
+    }
+
+    /// Packs the cursor into a number, e.g. to hand it to a remote caller.
+    pub fn to_u128(self) -> u128 {
+        ((self.segment as u128) << 64) | u128::from(self.from_hash)
+    }
+
+    pub fn from_u128(raw: u128) -> Self {
+        Cursor {
+            segment: (raw >> 64) as usize,
+            from_hash: raw as u64,
+        }
+    }
+
+    fn next_segment(self) -> Self {
+        Cursor {
+            segment: self.segment + 1,
+            from_hash: 0,
+        }
+    }
+}
+
+impl<K, V, B> ConcurrentHashMap<K, V, B>
+where
+    K: Eq + Hash,
+    B: BuildHasher + Default,
+{
+    /// Returns copies of up to `limit` entries starting at `cursor`, and the
+    /// cursor for the next page, or `None` once the whole map has been visited.
+    ///
+    /// Each page takes segment read locks one at a time and holds none once it
+    /// returns. Keys present for the whole scan are returned exactly once; keys
+    /// inserted or removed meanwhile may or may not be. Entries whose keys hash
+    /// equally are never split across pages, so a page can exceed `limit` in
+    /// the rare case of a full hash collision at its end.
+    ///
+    /// Panics if `limit` is zero.
+    pub fn scan(&self, cursor: Cursor, limit: usize) -> (Vec<(K, V)>, Option<Cursor>)
+    where
+        K: Clone,
+        V: Clone,
+    {
+        assert!(limit > 0, "scan with a limit of zero");
+        let mut page = Vec::new();
+        let mut cursor = cursor;
+        while cursor.segment < self.segments.len() {
+            let room = limit - page.len();
+            if room == 0 {
+                return (page, Some(cursor));
+            }
+            let segment = self.read_segment(cursor.segment);
+            let mut candidates: Vec<_> = segment
+                .iter()
+                .map(|(k, v)| (self.hash(k), k, v))
+                .filter(|&(hash, _, _)| hash >= cursor.from_hash)
+                .collect();
+            if candidates.len() <= room {
+                page.extend(
+                    candidates
+                        .into_iter()
+                        .map(|(_, k, v)| (k.clone(), v.clone())),
+                );
+                cursor = cursor.next_segment();
+                continue;
+            }
+            candidates.select_nth_unstable_by_key(room - 1, |&(hash, _, _)| hash);
+            let last = candidates[room - 1].0;
+            let (head, tail) = candidates.split_at(room);
+            page.extend(head.iter().map(|&(_, k, v)| (k.clone(), v.clone())));
+            page.extend(
+                tail.iter()
+                    .filter(|&&(hash, _, _)| hash == last)
+                    .map(|&(_, k, v)| (k.clone(), v.clone())),
+            );
+            let next = match last.checked_add(1) {
+                Some(from_hash) => Cursor {
+                    segment: cursor.segment,
+                    from_hash,
+                },
+                None => cursor.next_segment(),
+            };
+            return (page, Some(next));
+        }
+        (page, None)
+    }
+}
@@ -0,0 +1,97 @@
+use std::hash::{BuildHasher, Hash};
+
+use super::{ConcurrentHashMap, ReadGuard, WriteGuard};
+
+impl<N, K, V, B> ConcurrentHashMap<(N, K), V, B>
+where
+    N: Eq + Hash + Clone,
+    K: Eq + Hash + Clone,
+    B: BuildHasher + Default,
+{
+    /// A view of the entries whose key starts with `namespace`, addressed by the
+    /// rest of the key. Several views can share one map without seeing each
+    /// other's entries.
+    pub fn scoped(&self, namespace: N) -> Scoped<'_, N, K, V, B> {
+        Scoped {
+            map: self,
+            namespace,
+        }
+    }
+}
+
+/// A namespaced view of a map keyed by `(namespace, key)` pairs, created by
+/// `ConcurrentHashMap::scoped`. Lookups clone the namespace and key to build the
+/// full key.
+pub struct Scoped<'a, N: 'a, K: 'a, V: 'a, B: 'a> {
+    map: &'a ConcurrentHashMap<(N, K), V, B>,
+    namespace: N,
+}
+
+impl<'a, N, K, V, B> Scoped<'a, N, K, V, B>
+where
+    N: Eq + Hash + Clone,
+    K: Eq + Hash + Clone,
+    B: BuildHasher + Default,
+{
+    pub fn namespace(&self) -> &N {
+        &self.namespace
+    }
+
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        self.map.insert((self.namespace.clone(), key), value)
+    }
+
+    pub fn get(&self, key: &K) -> Option<ReadGuard<'a, (N, K), V, B>> {
+        self.map.get(&self.full_key(key))
+    }
+
+    pub fn get_mut(&self, key: &K) -> Option<WriteGuard<'a, (N, K), V, B>> {
+        self.map.get_mut(&self.full_key(key))
+    }
+
+    pub fn remove(&self, key: &K) -> Option<V> {
+        self.map.remove(&self.full_key(key))
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.map.contains(&self.full_key(key))
+    }
+
+    /// Counts this namespace's entries; this scans the whole map.
+    pub fn len(&self) -> usize {
+        self.map
+            .count_if(|full_key, _| full_key.0 == self.namespace)
+    }
+
+    /// Like `len`, this scans the map, stopping at the namespace's first entry.
+    pub fn is_empty(&self) -> bool {
+        !self.map.any(|full_key, _| full_key.0 == self.namespace)
+    }
+
+    /// Removes this namespace's unpinned entries and returns how many there were.
+    pub fn clear(&self) -> usize {
+        self.map
+            .remove_where(|full_key, _| full_key.0 == self.namespace)
+    }
+
+    /// Iterates over copies of this namespace's entries; see
+    /// `ConcurrentHashMap::iter` for the consistency guarantees. Each segment is
+    /// filtered under its read lock, so only this namespace's entries are cloned.
+    pub fn iter(&self) -> impl Iterator<Item = (K, V)> + '_
+    where
+        V: Clone,
+    {
+        let map = self.map;
+        (0..map.segments.len()).flat_map(move |i| {
+            map.read_segment(i)
+                .iter()
+                .filter(|&(full_key, _)| full_key.0 == self.namespace)
+                .map(|((_, k), v)| (k.clone(), v.clone()))
+                .collect::<Vec<_>>()
+        })
+    }
+
+    fn full_key(&self, key: &K) -> (N, K) {
+        (self.namespace.clone(), key.clone())
+    }
+}
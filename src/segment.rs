@@ -0,0 +1,360 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::{self, HashMap};
+use std::collections::TryReserveError;
+use std::hash::{BuildHasher, Hash};
+use std::iter;
+use std::ops::Index;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[cfg(feature = "entry-metadata")]
+use std::time::Instant;
+
+#[cfg(feature = "entry-metadata")]
+use metadata::{EntryMeta, EntryMetadata};
+
+type CloneTable<K, V, B> = fn(&HashMap<K, Slot<V>, B>) -> HashMap<K, Slot<V>, B>;
+
+pub(crate) type IntoEntries<K, V> =
+    iter::Map<hash_map::IntoIter<K, Slot<V>>, fn((K, Slot<V>)) -> (K, V)>;
+
+/// A table entry: the value, the pin count shared with its `PinGuard`s and,
+/// with entry-metadata, its metadata.
+pub(crate) struct Slot<V> {
+    pub(crate) value: V,
+    pins: Option<Arc<AtomicUsize>>,
+    #[cfg(feature = "entry-metadata")]
+    meta: Option<EntryMeta>,
+}
+
+impl<V> Slot<V> {
+    pub(crate) fn new(value: V) -> Self {
+        Slot {
+            value,
+            pins: None,
+            #[cfg(feature = "entry-metadata")]
+            meta: None,
+        }
+    }
+
+    #[cfg(feature = "entry-metadata")]
+    pub(crate) fn with_meta(mut self, created: Instant) -> Self {
+        self.meta = Some(EntryMeta::new(created));
+        self
+    }
+
+    #[cfg(feature = "entry-metadata")]
+    pub(crate) fn metadata(&self) -> Option<EntryMetadata> {
+        self.meta.as_ref().map(EntryMeta::snapshot)
+    }
+
+    #[cfg(feature = "entry-metadata")]
+    pub(crate) fn accessed(&self, now: Instant) -> EntryMetadata {
+        match self.meta {
+            Some(ref meta) => meta.accessed(now),
+            None => EntryMetadata {
+                created: now,
+                last_accessed: now,
+                access_count: 1,
+            },
+        }
+    }
+
+    #[inline]
+    fn is_pinned(&self) -> bool {
+        self.pins
+            .as_ref()
+            .is_some_and(|pins| pins.load(Ordering::Relaxed) != 0)
+    }
+}
+
+// Pins belong to one map, so copies start out unpinned.
+impl<V: Clone> Clone for Slot<V> {
+    fn clone(&self) -> Self {
+        Slot {
+            value: self.value.clone(),
+            pins: None,
+            #[cfg(feature = "entry-metadata")]
+            meta: self.meta.clone(),
+        }
+    }
+}
+
+/// A single shard's table. The table sits behind an `Arc` so that `cow_clone` can
+/// share it between maps; the first write to a shared table copies it. Slots in
+/// a shared table never hold live pins: pinning an entry unshares its table
+/// first, and tables holding pins are copied rather than shared. Metadata is
+/// updated through shared references, so with entry-metadata tables are always
+/// copied.
+pub(crate) struct Segment<K, V, B> {
+    table: Arc<HashMap<K, Slot<V>, B>>,
+    clone_table: Option<CloneTable<K, V, B>>,
+}
+
+impl<K, V, B> Segment<K, V, B> {
+    pub(crate) fn new(table: HashMap<K, Slot<V>, B>) -> Self {
+        Segment {
+            table: Arc::new(table),
+            clone_table: None,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn table_mut(&mut self) -> &mut HashMap<K, Slot<V>, B> {
+        if Arc::get_mut(&mut self.table).is_none() {
+            let clone_table = self
+                .clone_table
+                .expect("shared segment without a clone function");
+            self.table = Arc::new(clone_table(&self.table));
+        }
+        Arc::get_mut(&mut self.table).unwrap()
+    }
+
+    pub(crate) fn into_entries(self) -> IntoEntries<K, V> {
+        let clone_table = self.clone_table;
+        let table = Arc::try_unwrap(self.table).unwrap_or_else(|shared| {
+            let clone_table = clone_table.expect("shared segment without a clone function");
+            clone_table(&shared)
+        });
+        let entry: fn(_) -> _ = |(k, slot): (K, Slot<V>)| (k, slot.value);
+        table.into_iter().map(entry)
+    }
+
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.table.iter().map(|(k, slot)| (k, &slot.value))
+    }
+
+    #[cfg(feature = "entry-metadata")]
+    pub(crate) fn iter_metadata(&self) -> impl Iterator<Item = (&K, &V, Option<EntryMetadata>)> {
+        self.table
+            .iter()
+            .map(|(k, slot)| (k, &slot.value, slot.metadata()))
+    }
+
+    pub(crate) fn keys(&self) -> impl Iterator<Item = &K> {
+        self.table.keys()
+    }
+
+    #[cfg(feature = "rayon")]
+    pub(crate) fn values(&self) -> impl Iterator<Item = &V> {
+        self.table.values().map(|slot| &slot.value)
+    }
+
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = (&K, &mut V)> {
+        self.table_mut()
+            .iter_mut()
+            .map(|(k, slot)| (k, &mut slot.value))
+    }
+
+    pub(crate) fn drain(&mut self) -> impl Iterator<Item = (K, V)> + '_ {
+        self.table_mut().drain().map(|(k, slot)| (k, slot.value))
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.table_mut().clear();
+    }
+
+    // Whether any entry is pinned, for maps that have pins somewhere.
+    fn has_pins(&self) -> bool {
+        self.table.values().any(Slot::is_pinned)
+    }
+}
+
+impl<K: Eq + Hash, V, B: BuildHasher> Segment<K, V, B> {
+    #[inline]
+    pub(crate) fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.table.contains_key(key)
+    }
+
+    #[inline]
+    pub(crate) fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.table.get(key).map(|slot| &slot.value)
+    }
+
+    pub(crate) fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.table
+            .get_key_value(key)
+            .map(|(k, slot)| (k, &slot.value))
+    }
+
+    #[inline]
+    pub(crate) fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.table_mut().get_mut(key).map(|slot| &mut slot.value)
+    }
+
+    /// Stores `slot` if `key` is vacant. Otherwise only its value is stored, and
+    /// the entry keeps its pins and metadata.
+    pub(crate) fn insert(&mut self, key: K, slot: Slot<V>) -> Option<V> {
+        match self.table_mut().entry(key) {
+            hash_map::Entry::Occupied(mut entry) => {
+                Some(std::mem::replace(&mut entry.get_mut().value, slot.value))
+            }
+            hash_map::Entry::Vacant(entry) => {
+                entry.insert(slot);
+                None
+            }
+        }
+    }
+
+    /// Removes `key` whether or not it is pinned.
+    pub(crate) fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.table_mut()
+            .remove_entry(key)
+            .map(|(k, slot)| (k, slot.value))
+    }
+
+    #[inline]
+    pub(crate) fn is_pinned<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.table.get(key).is_some_and(Slot::is_pinned)
+    }
+
+    /// Counts a new pin on `key` and returns the count for its guard to release.
+    pub(crate) fn pin<Q>(&mut self, key: &Q) -> Option<Arc<AtomicUsize>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        let slot = self.table_mut().get_mut(key)?;
+        let pins = slot.pins.get_or_insert_with(Default::default).clone();
+        pins.fetch_add(1, Ordering::Relaxed);
+        Some(pins)
+    }
+
+    /// Removes and returns the unpinned entries for which `pred` returns true.
+    /// `pred` sees every entry, including pinned ones, and may modify values,
+    /// so a shared table is always copied.
+    pub(crate) fn extract_unpinned_mut<F>(&mut self, mut pred: F) -> Vec<(K, V)>
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        self.table_mut()
+            .extract_if(|k, slot| pred(k, &mut slot.value) && !slot.is_pinned())
+            .map(|(k, slot)| (k, slot.value))
+            .collect()
+    }
+
+    /// Removes and returns the unpinned entries for which `pred` returns true.
+    /// `pred` only sees unpinned entries. A shared table is only copied if some
+    /// entry matches, in which case `pred` sees the matching entries twice.
+    pub(crate) fn extract_unpinned<F>(&mut self, mut pred: F) -> Vec<(K, V)>
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        if Arc::get_mut(&mut self.table).is_none()
+            && !self
+                .table
+                .iter()
+                .any(|(k, slot)| !slot.is_pinned() && pred(k, &slot.value))
+        {
+            return Vec::new();
+        }
+        self.table_mut()
+            .extract_if(|k, slot| !slot.is_pinned() && pred(k, &slot.value))
+            .map(|(k, slot)| (k, slot.value))
+            .collect()
+    }
+
+    /// Moves the unpinned entries, along with their metadata, into `other`.
+    pub(crate) fn move_unpinned(&mut self, other: &mut Self) {
+        if self.table.values().all(Slot::is_pinned) {
+            return;
+        }
+        let other = other.table_mut();
+        for (k, mut slot) in self.table_mut().extract_if(|_, slot| !slot.is_pinned()) {
+            slot.pins = None;
+            other.insert(k, slot);
+        }
+    }
+
+    #[cfg(feature = "entry-metadata")]
+    pub(crate) fn accessed<Q>(&self, key: &Q, now: Instant) -> Option<EntryMetadata>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.table.get(key).map(|slot| slot.accessed(now))
+    }
+
+    #[cfg(feature = "entry-metadata")]
+    pub(crate) fn metadata<Q>(&self, key: &Q) -> Option<EntryMetadata>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + Hash,
+    {
+        self.table.get(key).and_then(Slot::metadata)
+    }
+
+    pub(crate) fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        self.table_mut().try_reserve(additional)
+    }
+
+    // A shared table is left alone; the first write will copy it at its live size.
+    pub(crate) fn shrink_to_fit(&mut self) {
+        if let Some(table) = Arc::get_mut(&mut self.table) {
+            table.shrink_to_fit();
+        }
+    }
+}
+
+impl<K: Clone, V: Clone, B: Clone> Segment<K, V, B> {
+    /// A segment for another map that shares this one's table, or gets its own
+    /// copy if `pinned` says the map has pins and this table holds some.
+    pub(crate) fn share(&mut self, pinned: bool) -> Self {
+        self.clone_table = Some(<HashMap<K, Slot<V>, B> as Clone>::clone);
+        let table = if cfg!(feature = "entry-metadata") || pinned && self.has_pins() {
+            Arc::new((*self.table).clone())
+        } else {
+            self.table.clone()
+        };
+        Segment {
+            table,
+            clone_table: self.clone_table,
+        }
+    }
+}
+
+impl<K, V, B, Q> Index<&Q> for Segment<K, V, B>
+where
+    K: Eq + Hash + Borrow<Q>,
+    B: BuildHasher,
+    Q: ?Sized + Eq + Hash,
+{
+    type Output = V;
+    fn index(&self, key: &Q) -> &V {
+        &self.table[key].value
+    }
+}
@@ -0,0 +1,44 @@
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diff<K> {
+    pub added: Vec<K>,
+    pub removed: Vec<K>,
+    pub changed: Vec<K>,
+}
+
+impl<K> Diff<K> {
+    pub(crate) fn empty() -> Self {
+        Diff {
+            added: Vec::new(),
+            removed: Vec::new(),
+            changed: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Computes the keys added, removed and changed going from `old` to `new`.
+pub fn diff<K, V, S, T>(old: &HashMap<K, V, S>, new: &HashMap<K, V, T>) -> Diff<K>
+where
+    K: Eq + Hash + Clone,
+    V: PartialEq,
+    S: BuildHasher,
+    T: BuildHasher,
+{
+    let mut diff = Diff::empty();
+    for (k, v) in new {
+        match old.get(k) {
+            None => diff.added.push(k.clone()),
+            Some(old_v) if old_v != v => diff.changed.push(k.clone()),
+            Some(_) => {}
+        }
+    }
+    diff.removed
+        .extend(old.keys().filter(|k| !new.contains_key(*k)).cloned());
+    diff
+}
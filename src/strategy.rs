@@ -0,0 +1,71 @@
+use proptest::arbitrary::{any, Arbitrary};
+use proptest::collection::{vec, SizeRange};
+use proptest::strategy::{BoxedStrategy, Just, Strategy};
+
+use std::fmt::Debug;
+use std::hash::{BuildHasher, Hash};
+
+use super::{ConcurrentHashMap, ConcurrentHashSet};
+
+/// Generates maps with between 1 and 32 segments, filled from `size` entries
+/// drawn from `key` and `value`; entries with duplicate keys collapse into one.
+/// Shrinking drops entries and moves toward a single segment.
+pub fn map_strategy<K, V, B, KS, VS, S>(
+    key: KS,
+    value: VS,
+    size: S,
+) -> impl Strategy<Value = ConcurrentHashMap<K, V, B>>
+where
+    K: Debug + Eq + Hash,
+    V: Debug,
+    B: BuildHasher + Default,
+    KS: Strategy<Value = K>,
+    VS: Strategy<Value = V>,
+    S: Into<SizeRange>,
+{
+    (0..6u32, vec((key, value), size)).prop_map(|(shift, entries)| {
+        let map = ConcurrentHashMap::with_options(0, B::default(), 1 << shift);
+        for (k, v) in entries {
+            map.insert(k, v);
+        }
+        map
+    })
+}
+
+/// Generates sets the way `map_strategy` generates maps.
+pub fn set_strategy<K, B, KS, S>(key: KS, size: S) -> impl Strategy<Value = ConcurrentHashSet<K, B>>
+where
+    K: Debug + Eq + Hash,
+    B: BuildHasher + Default,
+    KS: Strategy<Value = K>,
+    S: Into<SizeRange>,
+{
+    map_strategy(key, Just(()), size).prop_map(|table| ConcurrentHashSet { table })
+}
+
+impl<K, V, B> Arbitrary for ConcurrentHashMap<K, V, B>
+where
+    K: Arbitrary + Eq + Hash + 'static,
+    V: Arbitrary + 'static,
+    B: BuildHasher + Default + 'static,
+{
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        map_strategy(any::<K>(), any::<V>(), SizeRange::default()).boxed()
+    }
+}
+
+impl<K, B> Arbitrary for ConcurrentHashSet<K, B>
+where
+    K: Arbitrary + Eq + Hash + 'static,
+    B: BuildHasher + Default + 'static,
+{
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        set_strategy(any::<K>(), SizeRange::default()).boxed()
+    }
+}
@@ -0,0 +1,191 @@
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::hash_map::RandomState;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::ops::Deref;
+use std::str;
+
+use super::{ConcurrentHashMap, ReadGuard, WriteGuard};
+
+const INLINE_CAPACITY: usize = 22;
+
+/// An immutable string stored inline when it is at most 22 bytes long, and in a
+/// single heap allocation otherwise. It hashes and compares exactly like the
+/// `str` it holds, so maps keyed by it can be queried with a plain `&str`.
+#[derive(Clone)]
+pub struct SmallString(Repr);
+
+#[derive(Clone)]
+enum Repr {
+    Inline {
+        len: u8,
+        bytes: [u8; INLINE_CAPACITY],
+    },
+    Heap(Box<str>),
+}
+
+impl SmallString {
+    pub fn as_str(&self) -> &str {
+        match self.0 {
+            // SAFETY: inline bytes are only ever copied from a `str`, whole.
+            Repr::Inline { len, ref bytes } => unsafe {
+                str::from_utf8_unchecked(&bytes[..len as usize])
+            },
+            Repr::Heap(ref s) => s,
+        }
+    }
+
+    /// Whether the string is stored without a heap allocation.
+    pub fn is_inline(&self) -> bool {
+        match self.0 {
+            Repr::Inline { .. } => true,
+            Repr::Heap(_) => false,
+        }
+    }
+}
+
+impl<'a> From<&'a str> for SmallString {
+    fn from(s: &'a str) -> Self {
+        if s.len() > INLINE_CAPACITY {
+            return SmallString(Repr::Heap(s.into()));
+        }
+        let mut bytes = [0; INLINE_CAPACITY];
+        bytes[..s.len()].copy_from_slice(s.as_bytes());
+        SmallString(Repr::Inline {
+            len: s.len() as u8,
+            bytes,
+        })
+    }
+}
+
+impl From<String> for SmallString {
+    /// Reuses the `String`'s allocation for long strings.
+    fn from(s: String) -> Self {
+        if s.len() > INLINE_CAPACITY {
+            SmallString(Repr::Heap(s.into_boxed_str()))
+        } else {
+            SmallString::from(s.as_str())
+        }
+    }
+}
+
+impl Deref for SmallString {
+    type Target = str;
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Borrow<str> for SmallString {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Hash for SmallString {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl PartialEq for SmallString {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for SmallString {}
+
+impl PartialOrd for SmallString {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SmallString {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl Debug for SmallString {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Display for SmallString {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Display::fmt(self.as_str(), f)
+    }
+}
+
+/// A map with string keys, stored as `SmallString`s. Lookups take a `&str` and
+/// never allocate; inserting a key allocates only if it is longer than 22
+/// bytes.
+pub struct ConcurrentStringMap<V, B = RandomState> {
+    inner: ConcurrentHashMap<SmallString, V, B>,
+}
+
+impl<V> ConcurrentStringMap<V, RandomState> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<V, B: BuildHasher + Default> ConcurrentStringMap<V, B> {
+    pub fn with_options(capacity: usize, hash_builder: B, concurrency_level: usize) -> Self {
+        ConcurrentStringMap {
+            inner: ConcurrentHashMap::with_options(capacity, hash_builder, concurrency_level),
+        }
+    }
+
+    pub fn insert<S: Into<SmallString>>(&self, key: S, value: V) -> Option<V> {
+        self.inner.insert(key.into(), value)
+    }
+
+    pub fn get(&self, key: &str) -> Option<ReadGuard<'_, SmallString, V, B>> {
+        self.inner.get(key)
+    }
+
+    pub fn get_mut(&self, key: &str) -> Option<WriteGuard<'_, SmallString, V, B>> {
+        self.inner.get_mut(key)
+    }
+
+    pub fn insert_or_update<F, G>(&self, key: &str, insert: F, update: G)
+    where
+        F: FnOnce() -> V,
+        G: FnOnce(&mut V),
+    {
+        self.inner.insert_or_update(key.into(), insert, update)
+    }
+
+    pub fn remove(&self, key: &str) -> Option<V> {
+        self.inner.remove(key)
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.inner.contains(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub fn map(&self) -> &ConcurrentHashMap<SmallString, V, B> {
+        &self.inner
+    }
+}
+
+impl<V, B: BuildHasher + Default> Default for ConcurrentStringMap<V, B> {
+    fn default() -> Self {
+        ConcurrentStringMap {
+            inner: ConcurrentHashMap::default(),
+        }
+    }
+}
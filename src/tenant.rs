@@ -0,0 +1,72 @@
+use std::any::TypeId;
+use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
+
+use super::{ConcurrentHashMap, ReadGuard, Scoped, WriteGuard};
+
+impl<K, V, B> ConcurrentHashMap<(TypeId, K), V, B>
+where
+    K: Eq + Hash + Clone,
+    B: BuildHasher + Default,
+{
+    /// The view of this map belonging to tenant type `T`. Each tenant's entries
+    /// live under `T`'s `TypeId`, so a `TenantMap<T, ..>` can only ever reach
+    /// `T`'s keys, and handing one tenant's view to code expecting another's is
+    /// a type error.
+    pub fn tenant<T: 'static>(&self) -> TenantMap<'_, T, K, V, B> {
+        TenantMap {
+            scoped: self.scoped(TypeId::of::<T>()),
+            marker: PhantomData,
+        }
+    }
+}
+
+pub struct TenantMap<'a, T, K: 'a, V: 'a, B: 'a> {
+    scoped: Scoped<'a, TypeId, K, V, B>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<'a, T, K, V, B> TenantMap<'a, T, K, V, B>
+where
+    K: Eq + Hash + Clone,
+    B: BuildHasher + Default,
+{
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        self.scoped.insert(key, value)
+    }
+
+    pub fn get(&self, key: &K) -> Option<ReadGuard<'a, (TypeId, K), V, B>> {
+        self.scoped.get(key)
+    }
+
+    pub fn get_mut(&self, key: &K) -> Option<WriteGuard<'a, (TypeId, K), V, B>> {
+        self.scoped.get_mut(key)
+    }
+
+    pub fn remove(&self, key: &K) -> Option<V> {
+        self.scoped.remove(key)
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.scoped.contains(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.scoped.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scoped.is_empty()
+    }
+
+    pub fn clear(&self) -> usize {
+        self.scoped.clear()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (K, V)> + '_
+    where
+        V: Clone,
+    {
+        self.scoped.iter()
+    }
+}
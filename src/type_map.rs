@@ -0,0 +1,113 @@
+use std::any::{Any, TypeId};
+use std::collections::hash_map::RandomState;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+use super::{ConcurrentHashMap, ReadGuard, WriteGuard};
+
+type AnyValue = Box<dyn Any + Send + Sync>;
+
+/// A map holding at most one value of each type, keyed by the type itself.
+#[derive(Default)]
+pub struct ConcurrentTypeMap {
+    inner: ConcurrentHashMap<TypeId, AnyValue, RandomState>,
+}
+
+impl ConcurrentTypeMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores `value` as the map's `T`, returning the one it replaced.
+    pub fn insert<T: Any + Send + Sync>(&self, value: T) -> Option<T> {
+        self.inner
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .map(unbox)
+    }
+
+    pub fn get<T: Any + Send + Sync>(&self) -> Option<TypeReadGuard<'_, T>> {
+        self.inner
+            .get(&TypeId::of::<T>())
+            .map(|inner| TypeReadGuard {
+                inner,
+                marker: PhantomData,
+            })
+    }
+
+    pub fn get_mut<T: Any + Send + Sync>(&self) -> Option<TypeWriteGuard<'_, T>> {
+        self.inner
+            .get_mut(&TypeId::of::<T>())
+            .map(|inner| TypeWriteGuard {
+                inner,
+                marker: PhantomData,
+            })
+    }
+
+    /// Returns a guard on the map's `T`, storing `f()` first if there is none.
+    pub fn get_or_insert_with<T, F>(&self, f: F) -> TypeWriteGuard<'_, T>
+    where
+        T: Any + Send + Sync,
+        F: FnOnce() -> T,
+    {
+        let inner = self
+            .inner
+            .get_or_insert_with(TypeId::of::<T>(), || Box::new(f()))
+            .unwrap_or_else(|_| unreachable!("type maps are never closed"));
+        TypeWriteGuard {
+            inner,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn remove<T: Any + Send + Sync>(&self) -> Option<T> {
+        self.inner.remove(&TypeId::of::<T>()).map(unbox)
+    }
+
+    pub fn contains<T: Any + Send + Sync>(&self) -> bool {
+        self.inner.contains(&TypeId::of::<T>())
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+}
+
+// Values are only ever stored under their own `TypeId`, so the downcasts below
+// cannot fail.
+fn unbox<T: Any>(value: AnyValue) -> T {
+    *value.downcast::<T>().unwrap()
+}
+
+pub struct TypeReadGuard<'a, T> {
+    inner: ReadGuard<'a, TypeId, AnyValue, RandomState>,
+    marker: PhantomData<&'a T>,
+}
+
+impl<'a, T: Any> Deref for TypeReadGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.inner.downcast_ref().unwrap()
+    }
+}
+
+pub struct TypeWriteGuard<'a, T> {
+    inner: WriteGuard<'a, TypeId, AnyValue, RandomState>,
+    marker: PhantomData<&'a mut T>,
+}
+
+impl<'a, T: Any> Deref for TypeWriteGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.inner.downcast_ref().unwrap()
+    }
+}
+
+impl<'a, T: Any> DerefMut for TypeWriteGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.inner.downcast_mut().unwrap()
+    }
+}
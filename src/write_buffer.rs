@@ -0,0 +1,175 @@
+use std::collections::hash_map::{Entry, HashMap};
+use std::hash::{BuildHasher, Hash};
+
+use super::segment::Segment;
+use super::{ConcurrentHashMap, Error, Mutation};
+
+impl<K, V, B> ConcurrentHashMap<K, V, B>
+where
+    K: Eq + Hash,
+    B: BuildHasher + Default,
+{
+    /// Starts buffering writes for the calling thread; see `WriteBuffer`.
+    /// `merge(current, new)` combines a merged value into the one already present.
+    pub fn write_buffer<F>(&self, merge: F) -> WriteBuffer<'_, K, V, B, F>
+    where
+        F: FnMut(&mut V, V),
+    {
+        WriteBuffer {
+            map: self,
+            merge,
+            pending: (0..self.segments.len())
+                .map(|_| HashMap::with_hasher(B::default()))
+                .collect(),
+            len: 0,
+        }
+    }
+}
+
+enum Pending<V> {
+    Insert(V),
+    Merge(V),
+}
+
+/// Writes held back on one thread and applied to the map by `flush`, taking each
+/// segment's lock once for all of that segment's writes. Readers do not see
+/// buffered writes until they are flushed.
+///
+/// Writes to the same key are combined in the buffer, so `merge` should be
+/// associative, e.g. addition or `max`. Dropping the buffer flushes it, and
+/// discards any writes the map rejects.
+pub struct WriteBuffer<'a, K: 'a, V: 'a, B: 'a, F>
+where
+    K: Eq + Hash,
+    B: BuildHasher + Default,
+    F: FnMut(&mut V, V),
+{
+    map: &'a ConcurrentHashMap<K, V, B>,
+    merge: F,
+    pending: Vec<HashMap<K, Pending<V>, B>>,
+    len: usize,
+}
+
+impl<'a, K: 'a, V: 'a, B: 'a, F> WriteBuffer<'a, K, V, B, F>
+where
+    K: Eq + Hash,
+    B: BuildHasher + Default,
+    F: FnMut(&mut V, V),
+{
+    /// Buffers an insert that replaces any value, buffered or in the map.
+    pub fn insert(&mut self, key: K, value: V) {
+        let segment = self.map.get_segment(self.map.hash(&key));
+        if self.pending[segment]
+            .insert(key, Pending::Insert(value))
+            .is_none()
+        {
+            self.len += 1;
+        }
+    }
+
+    /// Buffers `value` to be merged into the key's value, or inserted if the key
+    /// is absent when the buffer is flushed.
+    pub fn merge(&mut self, key: K, value: V) {
+        let segment = self.map.get_segment(self.map.hash(&key));
+        match self.pending[segment].entry(key) {
+            Entry::Occupied(mut entry) => match *entry.get_mut() {
+                Pending::Insert(ref mut current) | Pending::Merge(ref mut current) => {
+                    (self.merge)(current, value)
+                }
+            },
+            Entry::Vacant(entry) => {
+                entry.insert(Pending::Merge(value));
+                self.len += 1;
+            }
+        }
+    }
+
+    /// Number of distinct keys with buffered writes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Applies every buffered write, one segment lock at a time, and reports them
+    /// to the observer once each segment's lock is released. The buffer is empty
+    /// afterwards; if the map has been closed, the writes it rejected are
+    /// discarded and `Error::Closed` is returned.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        let map = self.map;
+        let mut result = Ok(());
+        for (index, pending) in self.pending.iter_mut().enumerate() {
+            if pending.is_empty() {
+                continue;
+            }
+            let mut mutations = Vec::new();
+            {
+                let mut segment = map.write_segment(index);
+                if map.is_closed() {
+                    pending.clear();
+                    result = Err(Error::Closed);
+                    continue;
+                }
+                for (key, write) in pending.drain() {
+                    let mutation = match write {
+                        Pending::Insert(value) => map.insert_locked(&mut segment, key, value).1,
+                        Pending::Merge(value) => {
+                            merge_locked(map, &mut segment, key, value, &mut self.merge)
+                        }
+                    };
+                    mutations.extend(mutation);
+                }
+            }
+            for mutation in mutations {
+                map.notify(Some(mutation));
+            }
+        }
+        self.len = 0;
+        result
+    }
+}
+
+fn merge_locked<K, V, B, F>(
+    map: &ConcurrentHashMap<K, V, B>,
+    segment: &mut Segment<K, V, B>,
+    key: K,
+    value: V,
+    merge: &mut F,
+) -> Option<Mutation<K, V>>
+where
+    K: Eq + Hash,
+    B: BuildHasher + Default,
+    F: FnMut(&mut V, V),
+{
+    let hook = map.observer.as_ref();
+    match segment.table_mut().entry(key) {
+        Entry::Occupied(mut entry) => {
+            merge(&mut entry.get_mut().value, value);
+            hook.map(|hook| {
+                let (k, v) = hook.capture(entry.key(), &entry.get().value);
+                Mutation::Update(k, v)
+            })
+        }
+        Entry::Vacant(entry) => {
+            let mutation = hook.map(|hook| {
+                let k = (hook.clone_key)(entry.key());
+                Mutation::Insert(k, (hook.clone_value)(&value))
+            });
+            entry.insert(map.slot(value));
+            mutation
+        }
+    }
+}
+
+impl<'a, K: 'a, V: 'a, B: 'a, F> Drop for WriteBuffer<'a, K, V, B, F>
+where
+    K: Eq + Hash,
+    B: BuildHasher + Default,
+    F: FnMut(&mut V, V),
+{
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
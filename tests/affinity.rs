@@ -0,0 +1,75 @@
+extern crate poirot;
+
+use poirot::AffinityMap;
+use std::sync::Arc;
+use std::thread;
+
+#[test]
+fn affinity_map_routes_writes_to_local_shard() {
+    let affinity_map: Arc<AffinityMap<u32, u32>> = Arc::new(AffinityMap::new(2));
+    let handles: Vec<_> = (0..4)
+        .map(|t| {
+            let affinity_map = affinity_map.clone();
+            thread::spawn(move || {
+                for x in 0..100 {
+                    affinity_map.insert_local(t * 1000 + x, t);
+                }
+                affinity_map.insert_or_update_local(t * 1000, || 0, |v| *v += 10);
+                affinity_map.local_shard()
+            })
+        })
+        .collect();
+    let shards: Vec<usize> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+    assert_eq!(affinity_map.len(), 400);
+    for (t, &shard) in shards.iter().enumerate() {
+        let t = t as u32;
+        assert!((0..100).all(|x| affinity_map.shard(shard).contains(&(t * 1000 + x))));
+        let all = affinity_map.get_all(&(t * 1000));
+        assert_eq!(all.len(), 1);
+        assert_eq!((all[0].0, *all[0].1), (shard, t + 10));
+    }
+
+    let drained: usize = (0..affinity_map.shard_count())
+        .map(|i| affinity_map.drain_shard(i).len())
+        .sum();
+    assert_eq!(drained, 400);
+    assert!(affinity_map.is_empty());
+}
+
+#[test]
+fn affinity_map_keeps_an_entry_per_shard() {
+    let affinity_map: Arc<AffinityMap<u32, usize>> = Arc::new(AffinityMap::new(2));
+    // Threads are assigned shards round-robin, so a few are enough to write the
+    // key from both shards.
+    let mut written = [false; 2];
+    while written != [true, true] {
+        let affinity_map = affinity_map.clone();
+        let shard = thread::spawn(move || {
+            let shard = affinity_map.local_shard();
+            affinity_map.insert_local(7, shard);
+            assert_eq!(*affinity_map.get_local(&7).unwrap(), shard);
+            shard
+        });
+        written[shard.join().unwrap()] = true;
+    }
+
+    let all: Vec<(usize, usize)> = affinity_map
+        .get_all(&7)
+        .into_iter()
+        .map(|(i, v)| (i, *v))
+        .collect();
+    assert_eq!(all, vec![(0, 0), (1, 1)]);
+    assert_eq!(affinity_map.len(), 2);
+    assert_eq!(affinity_map.key_count(), 1);
+}
+
+#[cfg(feature = "numa")]
+#[test]
+fn affinity_map_per_numa_node() {
+    let affinity_map: AffinityMap<u32, u32> = AffinityMap::per_numa_node();
+    assert!(affinity_map.shard_count() >= 1);
+    assert!(affinity_map.local_shard() < affinity_map.shard_count());
+    affinity_map.insert_local(1, 1);
+    assert_eq!(*affinity_map.get_local(&1).unwrap(), 1);
+}
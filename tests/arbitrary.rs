@@ -0,0 +1,24 @@
+#![cfg(feature = "quickcheck")]
+
+#[macro_use]
+extern crate quickcheck;
+extern crate poirot;
+
+use poirot::{ConcurrentHashMap, ConcurrentHashSet};
+use quickcheck::Arbitrary;
+
+quickcheck! {
+    fn qc_arbitrary_map(map: ConcurrentHashMap<u8, u32>) -> bool {
+        let segments = map.segment_count();
+        (1..=32).contains(&segments)
+            && map.iter().count() == map.len()
+            && map.shrink().take(10).all(|smaller| {
+                smaller.len() <= map.len() && smaller.segment_count() == segments
+            })
+    }
+
+    fn qc_arbitrary_set(set: ConcurrentHashSet<u16>) -> bool {
+        let copy = set.clone();
+        set.to_vec().iter().all(|k| copy.contains(k))
+    }
+}
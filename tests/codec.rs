@@ -0,0 +1,52 @@
+extern crate poirot;
+
+#[cfg(feature = "deflate")]
+use poirot::DeflateCodec;
+use poirot::{CodecMap, ValueCodec};
+
+// Stores each string as its length-prefixed UTF-8 bytes.
+struct Utf8Codec;
+
+impl ValueCodec<String> for Utf8Codec {
+    fn encode(&self, value: &String) -> Vec<u8> {
+        let mut bytes = (value.len() as u32).to_le_bytes().to_vec();
+        bytes.extend_from_slice(value.as_bytes());
+        bytes
+    }
+
+    fn decode(&self, bytes: &[u8]) -> String {
+        let mut len = [0; 4];
+        len.copy_from_slice(&bytes[..4]);
+        let len = u32::from_le_bytes(len) as usize;
+        String::from_utf8(bytes[4..4 + len].to_vec()).unwrap()
+    }
+}
+
+#[test]
+fn codec_map_round_trip() {
+    let map = CodecMap::new(Utf8Codec);
+    assert_eq!(map.insert(1, "one".to_string()), None);
+    assert_eq!(map.insert(1, "uno".to_string()), Some("one".to_string()));
+    map.insert(2, "two".to_string());
+
+    assert_eq!(map.get(&1), Some("uno".to_string()));
+    assert_eq!(map.encoded(&1).unwrap().len(), 7);
+    assert_eq!(map.len(), 2);
+    assert_eq!(map.remove(&2), Some("two".to_string()));
+    assert!(!map.contains(&2));
+    assert_eq!(map.get(&2), None);
+}
+
+#[cfg(feature = "deflate")]
+#[test]
+fn deflate_codec() {
+    let map = CodecMap::new(DeflateCodec::new(Utf8Codec, 6));
+    let page = "<li>item</li>".repeat(500);
+    map.insert(1, page.clone());
+    assert_eq!(map.get(&1), Some(page.clone()));
+    assert!(map.encoded(&1).unwrap().len() < page.len() / 10);
+
+    let stats = map.codec().stats();
+    assert_eq!(stats.uncompressed_bytes, page.len() as u64 + 4);
+    assert!(stats.ratio() < 0.1);
+}
@@ -0,0 +1,47 @@
+#![cfg(feature = "deadlock-detection")]
+
+extern crate poirot;
+
+use poirot::{check_deadlocks, ConcurrentHashMap};
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn deadlock_reports_segments() {
+    let poirot_map = Arc::new(ConcurrentHashMap::new());
+    let (a, b) = (0..)
+        .map(|x| (0u64, x))
+        .find(|&(a, b)| poirot_map.segment_index(&a) != poirot_map.segment_index(&b))
+        .unwrap();
+    poirot_map.insert(a, 0);
+    poirot_map.insert(b, 0);
+    let barrier = Arc::new(Barrier::new(2));
+
+    for &(first, second) in &[(a, b), (b, a)] {
+        let poirot_map = poirot_map.clone();
+        let barrier = barrier.clone();
+        thread::spawn(move || {
+            let _held = poirot_map.get_mut(&first).unwrap();
+            barrier.wait();
+            let _blocked = poirot_map.get_mut(&second);
+        });
+    }
+
+    thread::sleep(Duration::from_millis(200));
+    let cycles = check_deadlocks();
+    assert_eq!(cycles.len(), 1);
+    let map_id = &*poirot_map as *const _ as usize;
+    let mut segments: Vec<_> = cycles[0]
+        .iter()
+        .map(|t| {
+            let id = t.waiting_on.unwrap();
+            assert_eq!(id.map, map_id);
+            id.segment
+        })
+        .collect();
+    segments.sort();
+    let mut expected = vec![poirot_map.segment_index(&a), poirot_map.segment_index(&b)];
+    expected.sort();
+    assert_eq!(segments, expected);
+}
@@ -0,0 +1,40 @@
+#![cfg(feature = "guard-timing")]
+
+extern crate poirot;
+
+use poirot::{set_guard_hold_threshold, set_panic_on_long_hold, ConcurrentHashMap};
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+use std::time::Duration;
+
+#[test]
+#[should_panic(expected = "WriteGuard held for")]
+fn guard_timing_panics_on_long_hold() {
+    set_guard_hold_threshold(Duration::from_millis(5));
+    set_panic_on_long_hold(true);
+
+    let poirot_map = ConcurrentHashMap::new();
+    poirot_map.insert(0, 0);
+    {
+        let _quick = poirot_map.get(&0).unwrap();
+    }
+    let _slow = poirot_map.get_mut(&0).unwrap();
+    thread::sleep(Duration::from_millis(20));
+}
+
+#[test]
+fn guard_timing_releases_the_lock_before_panicking() {
+    set_guard_hold_threshold(Duration::from_millis(5));
+    set_panic_on_long_hold(true);
+
+    let poirot_map = ConcurrentHashMap::new();
+    poirot_map.insert(0, 0);
+    let held = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut slow = poirot_map.get_mut(&0).unwrap();
+        *slow += 1;
+        thread::sleep(Duration::from_millis(20));
+    }));
+    assert!(held.is_err());
+    assert_eq!(*poirot_map.try_get(&0).unwrap().unwrap(), 1);
+    assert_eq!(*poirot_map.get(&0).unwrap(), 1);
+}
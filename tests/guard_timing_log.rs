@@ -0,0 +1,52 @@
+#![cfg(feature = "guard-timing")]
+
+// Kept apart from tests/guard_timing.rs, whose tests turn on panicking: the
+// guard-timing settings and the logger are process-wide.
+
+extern crate log;
+extern crate poirot;
+
+use log::{Level, Log, Metadata, Record};
+use poirot::{set_guard_hold_threshold, ConcurrentHashMap};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+struct Capture(Mutex<Vec<(Level, String)>>);
+
+impl Log for Capture {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        self.0
+            .lock()
+            .unwrap()
+            .push((record.level(), record.args().to_string()));
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: Capture = Capture(Mutex::new(Vec::new()));
+
+#[test]
+fn guard_timing_logs_long_holds() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(log::LevelFilter::Warn);
+    set_guard_hold_threshold(Duration::from_millis(5));
+
+    let poirot_map = ConcurrentHashMap::new();
+    poirot_map.insert(0, 0);
+    drop(poirot_map.get(&0).unwrap());
+    {
+        let _slow = poirot_map.get_mut(&0).unwrap();
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    let records = LOGGER.0.lock().unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].0, Level::Warn);
+    assert!(records[0].1.contains("WriteGuard held for"));
+}
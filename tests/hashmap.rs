@@ -2,7 +2,16 @@ extern crate poirot;
 #[macro_use]
 extern crate quickcheck;
 
-use poirot::ConcurrentHashMap;
+use poirot::{
+    diff, ConcurrentHashMap, Cursor, EntryRef, Error, GetOrInsertError, Observer, OpLog, Operation,
+    SequenceError, TenantMap,
+};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
 quickcheck! {
     fn qc_hashmap_insert(xs: Vec<u64>) -> bool {
@@ -41,3 +50,994 @@ fn hashmap_mutate() {
         assert_eq!(*poirot_map.get(&x).unwrap(), 1024);
     }
 }
+
+#[derive(Default)]
+struct CountingObserver {
+    inserts: AtomicUsize,
+    updates: AtomicUsize,
+    removes: AtomicUsize,
+}
+
+impl Observer<u32, u32> for CountingObserver {
+    fn on_insert(&self, _key: &u32, _value: &u32) {
+        self.inserts.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn on_update(&self, _key: &u32, _value: &u32) {
+        self.updates.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn on_remove(&self, _key: &u32, _value: &u32) {
+        self.removes.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn hashmap_observer() {
+    let counts = Arc::new(CountingObserver::default());
+    let poirot_map = ConcurrentHashMap::new().with_observer(counts.clone());
+
+    for x in 0..8 {
+        poirot_map.insert(x, 0);
+    }
+    poirot_map.insert(0, 1);
+    poirot_map.insert_or_update(1, || 0, |v| *v += 1);
+    poirot_map.insert_or_update(8, || 0, |v| *v += 1);
+    poirot_map.remove(&2);
+    poirot_map.remove(&100);
+
+    assert_eq!(counts.inserts.load(Ordering::SeqCst), 9);
+    assert_eq!(counts.updates.load(Ordering::SeqCst), 2);
+    assert_eq!(counts.removes.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn hashmap_oplog_replication() {
+    let log = Arc::new(OpLog::with_capacity(64));
+    let primary = ConcurrentHashMap::new().with_observer(log.clone());
+    let replica = ConcurrentHashMap::new();
+
+    for x in 0..8 {
+        primary.insert(x, x);
+    }
+    assert_eq!(replica.apply(log.ops_since(0)), Ok(Some(7)));
+
+    let synced = log.next_sequence();
+    primary.remove(&3);
+    primary.insert(4, 40);
+    assert_eq!(replica.apply(log.ops_since(synced)), Ok(Some(synced + 1)));
+
+    assert!(!replica.contains(&3));
+    assert_eq!(*replica.get(&4).unwrap(), 40);
+    assert_eq!(*replica.get(&7).unwrap(), 7);
+    assert_eq!(log.first_sequence(), Some(0));
+}
+
+#[test]
+fn hashmap_oplog_apply_out_of_sequence() {
+    let replica = ConcurrentHashMap::new();
+    assert_eq!(replica.apply(Vec::new()), Ok(None));
+    let ops = vec![
+        (4, Operation::Insert(1, 10)),
+        (5, Operation::Insert(2, 20)),
+        (7, Operation::Remove(1)),
+        (8, Operation::Insert(3, 30)),
+    ];
+    let error = replica.apply(ops).unwrap_err();
+    assert_eq!(
+        error,
+        SequenceError {
+            expected: 6,
+            found: 7
+        }
+    );
+    assert_eq!(*replica.get(&1).unwrap(), 10);
+    assert_eq!(*replica.get(&2).unwrap(), 20);
+    assert!(!replica.contains(&3));
+}
+
+fn replay<K, V>(log: &OpLog<K, V>, replica: &ConcurrentHashMap<K, V>, from: u64) -> u64
+where
+    K: Clone + Eq + std::hash::Hash,
+    V: Clone,
+{
+    replica.apply(log.ops_since(from)).unwrap();
+    log.next_sequence()
+}
+
+#[test]
+fn hashmap_oplog_replicates_segment_moves() {
+    let log = Arc::new(OpLog::with_capacity(1024));
+    let primary: ConcurrentHashMap<u32, u32> =
+        ConcurrentHashMap::with_options(64, RandomState::new(), 4).with_observer(log.clone());
+    let replica = ConcurrentHashMap::new();
+    for x in 0..64 {
+        primary.insert(x, x);
+    }
+    let synced = replay(&log, &replica, 0);
+
+    let exported = primary.export_segment(1);
+    let synced = replay(&log, &replica, synced);
+    assert_eq!(replica.snapshot(), primary.snapshot());
+
+    let changed = exported.into_iter().map(|(k, v)| (k, v * 10));
+    assert!(primary.import_segment(1, changed).is_empty());
+    replay(&log, &replica, synced);
+    assert_eq!(replica.snapshot(), primary.snapshot());
+    assert_eq!(replica.len(), 64);
+}
+
+#[test]
+fn hashmap_oplog_replicates_nested_writes() {
+    let log = Arc::new(OpLog::with_capacity(1024));
+    let primary: ConcurrentHashMap<u32, ConcurrentHashMap<u32, u32>> =
+        ConcurrentHashMap::with_options(16, RandomState::new(), 2).with_observer(log.clone());
+    let replica = ConcurrentHashMap::new();
+    for x in 0..32 {
+        primary.insert_nested(x % 4, x, x).unwrap();
+    }
+    primary.remove_nested(&0, &4);
+    for x in (1..32).step_by(4) {
+        primary.remove_nested(&1, &x);
+    }
+    replay(&log, &replica, 0);
+
+    assert!(!replica.contains(&1));
+    assert_eq!(replica.len(), primary.len());
+    for outer in 0..4 {
+        for inner in 0..32 {
+            assert_eq!(
+                replica.get_nested(&outer, &inner),
+                primary.get_nested(&outer, &inner)
+            );
+        }
+    }
+}
+
+#[test]
+fn hashmap_snapshot_diff() {
+    let poirot_map = ConcurrentHashMap::new();
+    for x in 0..8 {
+        poirot_map.insert(x, x);
+    }
+    let before = poirot_map.snapshot();
+
+    poirot_map.insert(8, 8);
+    poirot_map.insert(1, 10);
+    poirot_map.remove(&2);
+
+    let mut live = poirot_map.diff(&before);
+    live.added.sort();
+    live.removed.sort();
+    live.changed.sort();
+    assert_eq!(live.added, vec![8]);
+    assert_eq!(live.removed, vec![2]);
+    assert_eq!(live.changed, vec![1]);
+
+    assert_eq!(diff(&before, &poirot_map.snapshot()), live);
+    assert!(diff(&before, &before).is_empty());
+}
+
+#[test]
+fn hashmap_cow_clone() {
+    let poirot_map = ConcurrentHashMap::new();
+    for x in 0..64 {
+        poirot_map.insert(x, x);
+    }
+    let frozen = poirot_map.cow_clone();
+
+    poirot_map.insert(0, 100);
+    poirot_map.remove(&1);
+    *poirot_map.get_mut(&2).unwrap() = 200;
+    frozen.insert(64, 64);
+
+    assert_eq!(*frozen.get(&0).unwrap(), 0);
+    assert_eq!(*frozen.get(&1).unwrap(), 1);
+    assert_eq!(*frozen.get(&2).unwrap(), 2);
+    assert!(!poirot_map.contains(&64));
+    assert_eq!(*poirot_map.get(&2).unwrap(), 200);
+    assert_eq!(frozen.into_iter().count(), 65);
+    assert_eq!(poirot_map.into_iter().count(), 63);
+}
+
+#[test]
+fn hashmap_segment_export_import() {
+    let source = ConcurrentHashMap::with_options(64, RandomState::new(), 4);
+    for x in 0..256 {
+        source.insert(x, x);
+    }
+    let target = ConcurrentHashMap::with_options(64, RandomState::new(), 4);
+
+    let mut moved = 0;
+    for i in 0..source.segment_count() {
+        let entries = source.export_segment(i);
+        moved += entries.len();
+        for (k, v) in target.import_segment(i, entries) {
+            target.insert(k, v);
+        }
+    }
+
+    assert_eq!(moved, 256);
+    assert_eq!(source.into_iter().count(), 0);
+    assert!((0..256).all(|x| *target.get(&x).unwrap() == x));
+    assert!((0..256).all(|x| target.segment_index(&x) < target.segment_count()));
+}
+
+#[test]
+fn hashmap_segment_moves_are_observed() {
+    let counts = Arc::new(CountingObserver::default());
+    let source: ConcurrentHashMap<u32, u32> =
+        ConcurrentHashMap::with_options(64, RandomState::new(), 4).with_observer(counts.clone());
+    for x in 0..64 {
+        source.insert(x, x);
+    }
+    let entries = source.export_segment(0);
+    assert_eq!(counts.removes.load(Ordering::SeqCst), entries.len());
+
+    let exported = entries.len();
+    let mut entries = entries;
+    entries.push((entries[0].0, 100));
+    assert!(source.import_segment(0, entries).is_empty());
+    assert_eq!(counts.inserts.load(Ordering::SeqCst), 64 + exported);
+    assert_eq!(counts.updates.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn hashmap_fallible_allocation() {
+    let poirot_map =
+        ConcurrentHashMap::with_options(0, RandomState::new(), 2).with_capacity_limit(16);
+    assert!(poirot_map.try_reserve(16).is_ok());
+
+    let mut inserted = 0;
+    for x in 0..256 {
+        match poirot_map.try_insert_within_capacity(x, x) {
+            Ok(None) => inserted += 1,
+            Ok(Some(_)) => unreachable!(),
+            Err(e) => {
+                assert_eq!(e.error, Error::CapacityExceeded);
+                assert_eq!(e.into_entry(), (x, x));
+            }
+        }
+    }
+    assert_eq!(inserted, 16);
+    assert_eq!(poirot_map.len(), 16);
+    let (key, _) = poirot_map.iter().next().unwrap();
+    assert_eq!(poirot_map.try_insert_within_capacity(key, 1), Ok(Some(key)));
+
+    poirot_map.shrink_to_fit();
+    assert!(poirot_map.try_insert_within_capacity(1024, 0).is_err());
+
+    let unlimited = ConcurrentHashMap::with_options(0, RandomState::new(), 2);
+    assert!((0..256).all(|x| unlimited.try_insert_within_capacity(x, x) == Ok(None)));
+
+    assert!(unlimited.try_reserve(usize::MAX).is_err());
+}
+
+#[test]
+fn hashmap_lock_key() {
+    let poirot_map = Arc::new(ConcurrentHashMap::new());
+    let busy = Arc::new(AtomicUsize::new(0));
+    let creates = Arc::new(AtomicUsize::new(0));
+
+    let handles: Vec<_> = (0..8)
+        .map(|_| {
+            let poirot_map = poirot_map.clone();
+            let busy = busy.clone();
+            let creates = creates.clone();
+            thread::spawn(move || {
+                let _slot = poirot_map.lock_key(&"artifact");
+                assert_eq!(busy.fetch_add(1, Ordering::SeqCst), 0);
+                if !poirot_map.contains("artifact") {
+                    creates.fetch_add(1, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(5));
+                    poirot_map.insert("artifact", 1);
+                }
+                busy.fetch_sub(1, Ordering::SeqCst);
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    assert_eq!(creates.load(Ordering::SeqCst), 1);
+    let _other = poirot_map.lock_key(&"other");
+    let _artifact = poirot_map.lock_key(&"artifact");
+}
+
+#[test]
+fn hashmap_pin() {
+    let poirot_map = ConcurrentHashMap::with_options(64, RandomState::new(), 2);
+    for x in 0..16 {
+        poirot_map.insert(x, x);
+    }
+    assert!(poirot_map.pin(&100).is_none());
+
+    {
+        let _pin = poirot_map.pin(&3).unwrap();
+        let _again = poirot_map.pin(&3).unwrap();
+        assert_eq!(poirot_map.remove(&3), None);
+        let exported: usize = (0..poirot_map.segment_count())
+            .map(|i| poirot_map.export_segment(i).len())
+            .sum();
+        assert_eq!(exported, 15);
+        assert_eq!(*poirot_map.get(&3).unwrap(), 3);
+    }
+
+    assert_eq!(poirot_map.remove(&3), Some(3));
+}
+
+#[derive(Default)]
+struct CollidingHasher;
+
+impl Hasher for CollidingHasher {
+    fn finish(&self) -> u64 {
+        0
+    }
+
+    fn write(&mut self, _: &[u8]) {}
+}
+
+#[test]
+fn hashmap_pin_colliding_keys() {
+    let poirot_map: ConcurrentHashMap<u64, u64, BuildHasherDefault<CollidingHasher>> =
+        ConcurrentHashMap::with_options(16, Default::default(), 2);
+    for x in 0..4 {
+        poirot_map.insert(x, x);
+    }
+
+    let _pin = poirot_map.pin(&1).unwrap();
+    assert_eq!(poirot_map.remove(&2), Some(2));
+    assert_eq!(poirot_map.remove(&1), None);
+    assert_eq!(poirot_map.retain_mut(|_, _| false), 2);
+    assert_eq!(poirot_map.len(), 1);
+}
+
+#[test]
+fn hashmap_lock_colliding_keys() {
+    let poirot_map: Arc<ConcurrentHashMap<u64, u64, BuildHasherDefault<CollidingHasher>>> =
+        Arc::new(ConcurrentHashMap::with_options(16, Default::default(), 2));
+    let _one = poirot_map.lock_key(&1);
+    let _two = poirot_map.lock_key(&2);
+
+    let map = poirot_map.clone();
+    let waiter = thread::spawn(move || {
+        let _one = map.lock_key(&1);
+    });
+    thread::sleep(Duration::from_millis(5));
+    assert!(!waiter.is_finished());
+    drop(_two);
+    thread::sleep(Duration::from_millis(5));
+    assert!(!waiter.is_finished());
+    drop(_one);
+    waiter.join().unwrap();
+}
+
+#[test]
+fn hashmap_nested() {
+    let poirot_map: Arc<ConcurrentHashMap<u64, ConcurrentHashMap<u64, u64>>> =
+        Arc::new(ConcurrentHashMap::new());
+
+    let handles: Vec<_> = (0..4)
+        .map(|t| {
+            let poirot_map = poirot_map.clone();
+            thread::spawn(move || {
+                for round in 0..256 {
+                    let inner = t * 1024 + round;
+                    assert_eq!(
+                        poirot_map.insert_nested(round % 4, inner, round).unwrap(),
+                        None
+                    );
+                    assert_eq!(poirot_map.get_nested(&(round % 4), &inner), Some(round));
+                    assert_eq!(poirot_map.remove_nested(&(round % 4), &inner), Some(round));
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert!(poirot_map.is_empty());
+
+    poirot_map.insert_nested(1, 1, 1).unwrap();
+    poirot_map.insert_nested(1, 2, 2).unwrap();
+    assert_eq!(poirot_map.remove_nested(&1, &1), Some(1));
+    assert_eq!(poirot_map.len(), 1);
+    assert_eq!(poirot_map.get(&1).unwrap().len(), 1);
+    assert_eq!(poirot_map.remove_nested(&1, &3), None);
+    assert_eq!(poirot_map.remove_nested(&2, &1), None);
+}
+
+#[test]
+fn hashmap_close() {
+    let counts = Arc::new(CountingObserver::default());
+    let poirot_map: Arc<ConcurrentHashMap<u32, u32>> = Arc::new(
+        ConcurrentHashMap::with_options(64, RandomState::new(), 2).with_observer(counts.clone()),
+    );
+    for x in 0..16 {
+        poirot_map.insert(x, x);
+    }
+
+    let reader = {
+        let poirot_map = poirot_map.clone();
+        let guard_held = Arc::new(AtomicUsize::new(0));
+        let signal = guard_held.clone();
+        let handle = thread::spawn(move || {
+            let guard = poirot_map.get(&0).unwrap();
+            signal.store(1, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(20));
+            *guard
+        });
+        while guard_held.load(Ordering::SeqCst) == 0 {
+            thread::yield_now();
+        }
+        handle
+    };
+    let _pin = poirot_map.pin(&5).unwrap();
+
+    let mut drained = poirot_map.close();
+    drained.sort();
+    assert_eq!(reader.join().unwrap(), 0);
+    assert_eq!(drained.len(), 15);
+    assert!(!drained.contains(&(5, 5)));
+    assert_eq!(counts.removes.load(Ordering::SeqCst), 15);
+
+    assert!(poirot_map.is_closed());
+    assert_eq!(
+        poirot_map.try_insert(1, 1).unwrap_err().error,
+        Error::Closed
+    );
+    assert_eq!(poirot_map.insert(1, 1), None);
+    poirot_map.insert_or_update(2, || 0, |v| *v += 1);
+    assert_eq!(
+        poirot_map.try_insert_or_update(2, || 0, |v| *v += 1),
+        Err(Error::Closed)
+    );
+    let mut buffer = poirot_map.write_buffer(|v, x| *v += x);
+    buffer.insert(3, 3);
+    assert_eq!(buffer.flush(), Err(Error::Closed));
+    assert!(buffer.is_empty());
+    drop(buffer);
+    assert_eq!(poirot_map.len(), 1);
+    assert_eq!(*poirot_map.get(&5).unwrap(), 5);
+}
+
+#[test]
+fn hashmap_close_rejects_nested_writes() {
+    let poirot_map: ConcurrentHashMap<u32, ConcurrentHashMap<u32, u32>> = ConcurrentHashMap::new();
+    poirot_map.insert_nested(1, 1, 1).unwrap();
+    let _pin = poirot_map.pin(&1).unwrap();
+    poirot_map.close();
+
+    assert_eq!(poirot_map.insert_nested(1, 2, 2), Err(Error::Closed));
+    assert_eq!(poirot_map.insert_nested(2, 2, 2), Err(Error::Closed));
+    assert_eq!(poirot_map.get_nested(&1, &2), None);
+}
+
+#[test]
+fn hashmap_try_get() {
+    let poirot_map = ConcurrentHashMap::with_options(64, RandomState::new(), 2);
+    poirot_map.insert(1, 1);
+    assert_eq!(poirot_map.try_get(&1).unwrap().map(|v| *v), Some(1));
+    assert!(poirot_map.try_get(&2).unwrap().is_none());
+
+    let writer = poirot_map.get_mut(&1).unwrap();
+    assert_eq!(poirot_map.try_get(&1).unwrap_err(), Error::WouldBlock);
+    assert_eq!(poirot_map.try_get_mut(&1).unwrap_err(), Error::WouldBlock);
+    assert_eq!(
+        poirot_map
+            .get_timeout(&1, Duration::from_millis(5))
+            .unwrap_err(),
+        Error::Timeout
+    );
+    drop(writer);
+
+    let reader = poirot_map.get(&1).unwrap();
+    assert_eq!(
+        *poirot_map
+            .get_timeout(&1, Duration::from_millis(5))
+            .unwrap()
+            .unwrap(),
+        1
+    );
+    assert_eq!(
+        poirot_map
+            .get_mut_timeout(&1, Duration::from_millis(5))
+            .unwrap_err(),
+        Error::Timeout
+    );
+    drop(reader);
+    *poirot_map.try_get_mut(&1).unwrap().unwrap() += 1;
+    assert_eq!(*poirot_map.get(&1).unwrap(), 2);
+}
+
+#[test]
+fn hashmap_insert_and_get() {
+    let log = Arc::new(OpLog::with_capacity(16));
+    let poirot_map = ConcurrentHashMap::new().with_observer(log.clone());
+
+    {
+        let (previous, mut guard) = poirot_map.insert_and_get(1, vec![1]).unwrap();
+        assert_eq!(previous, None);
+        guard.push(2);
+        assert!(log.ops_since(0).is_empty());
+    }
+    let (previous, guard) = poirot_map.insert_and_get(1, vec![3]).unwrap();
+    assert_eq!(previous, Some(vec![1, 2]));
+    drop(guard);
+
+    let ops: Vec<_> = log.ops_since(0).into_iter().map(|(_, op)| op).collect();
+    assert_eq!(
+        ops,
+        vec![
+            Operation::Insert(1, vec![1, 2]),
+            Operation::Insert(1, vec![3])
+        ]
+    );
+
+    poirot_map.close();
+    let rejected = poirot_map.insert_and_get(2, vec![4]).err().unwrap();
+    assert_eq!(
+        (rejected.error, rejected.key, rejected.value),
+        (Error::Closed, 2, vec![4])
+    );
+}
+
+#[test]
+fn hashmap_get_or_insert() {
+    let counts = Arc::new(CountingObserver::default());
+    let poirot_map = ConcurrentHashMap::new().with_observer(counts.clone());
+
+    *poirot_map.get_or_insert(1, 10).unwrap() += 1;
+    assert_eq!(*poirot_map.get_or_insert(1, 20).unwrap(), 11);
+    assert_eq!(*poirot_map.get_or_insert_with(2, || 30).unwrap(), 30);
+    assert_eq!(
+        *poirot_map
+            .get_or_insert_with(2, || panic!("key exists"))
+            .unwrap(),
+        30
+    );
+    assert_eq!(counts.inserts.load(Ordering::SeqCst), 2);
+    assert_eq!(counts.updates.load(Ordering::SeqCst), 0);
+
+    poirot_map.close();
+    assert_eq!(poirot_map.get_or_insert(3, 30).err(), Some(Error::Closed));
+}
+
+#[test]
+fn hashmap_count_if() {
+    let poirot_map = ConcurrentHashMap::new();
+    for x in 0..100u32 {
+        poirot_map.insert(x, x * 2);
+    }
+    assert_eq!(poirot_map.count_if(|_, v| *v >= 100), 50);
+    assert_eq!(poirot_map.count_if(|k, _| k.is_multiple_of(10)), 10);
+    #[cfg(feature = "rayon")]
+    assert_eq!(poirot_map.par_count_if(|_, v| *v >= 100), 50);
+}
+
+#[test]
+fn hashmap_any_all() {
+    let poirot_map = ConcurrentHashMap::new();
+    assert!(!poirot_map.any(|_, _: &u32| true));
+    assert!(poirot_map.all(|_, _: &u32| false));
+    for x in 0..100u32 {
+        poirot_map.insert(x, x);
+    }
+    assert!(poirot_map.any(|_, v| *v == 99));
+    assert!(!poirot_map.any(|_, v| *v >= 100));
+    assert!(poirot_map.all(|k, v| k == v));
+    assert!(!poirot_map.all(|_, v| *v < 99));
+
+    let mut visited = 0;
+    assert!(poirot_map.any(|_, _| {
+        visited += 1;
+        true
+    }));
+    assert_eq!(visited, 1);
+}
+
+#[test]
+fn hashmap_max_min_by() {
+    let poirot_map = ConcurrentHashMap::new();
+    assert_eq!(poirot_map.max_by(|a: (&u32, &u32), b| a.1.cmp(b.1)), None);
+    for x in 0..100u32 {
+        poirot_map.insert(x, (x * 37) % 101);
+    }
+    let hottest = poirot_map.max_by(|a, b| a.1.cmp(b.1));
+    assert_eq!(hottest.map(|(_, v)| v), Some(100));
+    assert_eq!(poirot_map.min_by(|a, b| a.0.cmp(b.0)), Some((0, 0)));
+    #[cfg(feature = "rayon")]
+    {
+        assert_eq!(poirot_map.par_max_by(|a, b| a.1.cmp(b.1)), hottest);
+        assert_eq!(poirot_map.par_min_by(|a, b| a.0.cmp(b.0)), Some((0, 0)));
+    }
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn hashmap_aggregate() {
+    let poirot_map = ConcurrentHashMap::new();
+    assert_eq!(poirot_map.sum_values(), 0u64);
+    for x in 1..=100u64 {
+        poirot_map.insert(x, x);
+    }
+    assert_eq!(poirot_map.sum_values(), 5050);
+    let (count, total) = poirot_map.aggregate(
+        || (0, 0),
+        |(n, sum), _, v| (n + 1, sum + v),
+        |a, b| (a.0 + b.0, a.1 + b.1),
+    );
+    assert_eq!(total / count, 50);
+}
+
+#[test]
+fn hashmap_top_k() {
+    let poirot_map = ConcurrentHashMap::new();
+    for x in 0..1000u32 {
+        poirot_map.insert(x, (x * 7919) % 1000);
+    }
+    let top = poirot_map.top_k(3, |_, v| *v);
+    let scores: Vec<u32> = top.iter().map(|&(_, v)| v).collect();
+    assert_eq!(scores, vec![999, 998, 997]);
+    assert!(top.iter().all(|&(k, v)| (k * 7919) % 1000 == v));
+    assert_eq!(poirot_map.top_k(2000, |k, _| *k).len(), 1000);
+    assert!(poirot_map.top_k(0, |k, _| *k).is_empty());
+}
+
+#[test]
+fn hashmap_into_segment_vecs() {
+    let poirot_map = ConcurrentHashMap::with_options(64, RandomState::new(), 4);
+    for x in 0..64u32 {
+        poirot_map.insert(x, x);
+    }
+    let expected: Vec<usize> = (0..64u32).map(|x| poirot_map.segment_index(&x)).collect();
+    let shards = poirot_map.into_segment_vecs();
+    assert_eq!(shards.len(), 4);
+    assert_eq!(shards.iter().map(Vec::len).sum::<usize>(), 64);
+    for (i, shard) in shards.iter().enumerate() {
+        assert!(shard.iter().all(|&(k, _)| expected[k as usize] == i));
+    }
+}
+
+quickcheck! {
+    fn qc_hashmap_sorted_vec(xs: Vec<u64>) -> bool {
+        let poirot_map = ConcurrentHashMap::new();
+        xs.iter().cloned().for_each(|k| {poirot_map.insert(k, k);});
+        let mut expected = xs;
+        expected.sort();
+        expected.dedup();
+        let expected: Vec<_> = expected.into_iter().map(|k| (k, k)).collect();
+        poirot_map.to_sorted_vec() == expected && poirot_map.into_sorted_vec() == expected
+    }
+}
+
+#[test]
+fn hashmap_live_iter() {
+    let poirot_map = ConcurrentHashMap::with_options(64, RandomState::new(), 4);
+    for x in 0..64u32 {
+        poirot_map.insert(x, x);
+    }
+
+    let mut seen = Vec::new();
+    for (k, v) in poirot_map.iter() {
+        assert_eq!(k, v);
+        seen.push(k);
+        // Writing to the map mid-iteration must not deadlock.
+        poirot_map.insert_or_update(k, || 0, |v| *v += 1);
+        *poirot_map.get_mut(&k).unwrap() -= 1;
+    }
+    seen.sort();
+    assert_eq!(seen, (0..64).collect::<Vec<_>>());
+}
+
+#[test]
+fn hashmap_write_guard_take_replace() {
+    let poirot_map = ConcurrentHashMap::new();
+    poirot_map.insert(1, vec![1, 2]);
+    {
+        let mut guard = poirot_map.get_mut(&1).unwrap();
+        assert_eq!(guard.replace(vec![3]), vec![1, 2]);
+        assert_eq!(*guard, vec![3]);
+        assert_eq!(guard.take(), vec![3]);
+    }
+    assert!(poirot_map.get(&1).unwrap().is_empty());
+}
+
+#[test]
+fn hashmap_get_mut_or_insert_default() {
+    let poirot_map = ConcurrentHashMap::new();
+    for word in "a b a c a b".split(' ') {
+        *poirot_map.get_mut_or_insert_default(word).unwrap() += 1;
+    }
+    assert_eq!(
+        poirot_map.to_sorted_vec(),
+        vec![("a", 3), ("b", 2), ("c", 1)]
+    );
+}
+
+#[test]
+fn hashmap_scoped() {
+    let poirot_map = ConcurrentHashMap::new();
+    let (alice, bob) = (poirot_map.scoped("alice"), poirot_map.scoped("bob"));
+    for x in 0..10u32 {
+        alice.insert(x, x);
+    }
+    bob.insert(0, 100);
+
+    assert_eq!(*alice.get(&0).unwrap(), 0);
+    assert_eq!(*bob.get(&0).unwrap(), 100);
+    assert!(!bob.contains(&1));
+    assert_eq!(alice.len(), 10);
+    assert_eq!(bob.iter().collect::<Vec<_>>(), vec![(0, 100)]);
+
+    *bob.get_mut(&0).unwrap() += 1;
+    assert_eq!(poirot_map.get(&("bob", 0)).map(|v| *v), Some(101));
+    assert_eq!(alice.remove(&9), Some(9));
+    assert_eq!(alice.clear(), 9);
+    assert!(alice.is_empty());
+    assert_eq!(bob.len(), 1);
+}
+
+#[test]
+fn hashmap_tenants() {
+    struct Acme;
+    struct Globex;
+
+    fn bill(tenant: &TenantMap<'_, Acme, &'static str, u32, RandomState>) -> u32 {
+        tenant.iter().map(|(_, v)| v).sum()
+    }
+
+    let poirot_map = ConcurrentHashMap::new();
+    let acme = poirot_map.tenant::<Acme>();
+    let globex = poirot_map.tenant::<Globex>();
+    acme.insert("cpu", 3);
+    acme.insert("disk", 4);
+    globex.insert("cpu", 10);
+
+    assert_eq!(bill(&acme), 7);
+    assert_eq!(*globex.get(&"cpu").unwrap(), 10);
+    assert_eq!(globex.len(), 1);
+    assert_eq!(acme.clear(), 2);
+    assert!(acme.is_empty());
+    assert_eq!(poirot_map.len(), 1);
+}
+
+#[test]
+fn hashmap_take() {
+    let counts = Arc::new(CountingObserver::default());
+    let poirot_map: ConcurrentHashMap<u32, u32> =
+        ConcurrentHashMap::with_options(64, RandomState::new(), 4).with_observer(counts.clone());
+    for x in 0..32 {
+        poirot_map.insert(x, x);
+    }
+    let _pin = poirot_map.pin(&7).unwrap();
+
+    let batch = poirot_map.take();
+    assert_eq!(batch.len(), 31);
+    assert_eq!(batch.segment_count(), poirot_map.segment_count());
+    for x in (0..32).filter(|&x| x != 7) {
+        assert_eq!(batch.segment_index(&x), poirot_map.segment_index(&x));
+        assert_eq!(*batch.get(&x).unwrap(), x);
+    }
+    assert_eq!(counts.removes.load(Ordering::SeqCst), 31);
+
+    assert_eq!(poirot_map.len(), 1);
+    assert!(poirot_map.contains(&7));
+    poirot_map.insert(40, 40);
+    assert_eq!(poirot_map.len(), 2);
+    assert!(!batch.contains(&40));
+}
+
+#[test]
+fn hashmap_retain_mut() {
+    let counts = Arc::new(CountingObserver::default());
+    let poirot_map: ConcurrentHashMap<u32, u32> =
+        ConcurrentHashMap::with_options(64, RandomState::new(), 4).with_observer(counts.clone());
+    for x in 0..16 {
+        poirot_map.insert(x, x % 4);
+    }
+    let _pin = poirot_map.pin(&4).unwrap();
+
+    let removed = poirot_map.retain_mut(|_, v| {
+        *v = v.saturating_sub(1);
+        *v > 0
+    });
+    assert_eq!(removed, 7);
+    assert_eq!(counts.removes.load(Ordering::SeqCst), 7);
+    assert_eq!(poirot_map.len(), 9);
+    assert_eq!(*poirot_map.get(&4).unwrap(), 0);
+    assert_eq!(*poirot_map.get(&3).unwrap(), 2);
+    assert!(!poirot_map.contains(&1));
+}
+
+#[test]
+fn hashmap_scan() {
+    let poirot_map = ConcurrentHashMap::with_options(64, RandomState::new(), 4);
+    for x in 0..100u32 {
+        poirot_map.insert(x, x);
+    }
+
+    let mut seen = Vec::new();
+    let mut cursor = Some(Cursor::start());
+    let mut pages = 0;
+    while let Some(current) = cursor {
+        let (page, next) = poirot_map.scan(Cursor::from_u128(current.to_u128()), 7);
+        assert!(page.len() <= 7);
+        seen.extend(page.into_iter().map(|(k, _)| k));
+        // Writes between pages are fine and do not disturb the scan.
+        poirot_map.insert(1000 + pages, 0);
+        poirot_map.remove(&(1000 + pages));
+        pages += 1;
+        cursor = next;
+    }
+    seen.sort();
+    assert_eq!(seen, (0..100).collect::<Vec<_>>());
+    assert!(pages >= 15);
+
+    let (everything, done) = poirot_map.scan(Cursor::start(), 1000);
+    assert_eq!(everything.len(), 100);
+    assert_eq!(done, None);
+}
+
+#[test]
+fn hashmap_get_or_try_insert_with() {
+    let counts = Arc::new(CountingObserver::default());
+    let poirot_map: ConcurrentHashMap<u32, u32> =
+        ConcurrentHashMap::with_options(16, RandomState::new(), 2).with_observer(counts.clone());
+
+    let failed = poirot_map.get_or_try_insert_with(1, || "x".parse::<u32>());
+    assert!(matches!(failed, Err(GetOrInsertError::Failed(_))));
+    assert!(!poirot_map.contains(&1));
+    assert_eq!(counts.inserts.load(Ordering::SeqCst), 0);
+
+    {
+        let mut guard = poirot_map
+            .get_or_try_insert_with(1, || "10".parse::<u32>())
+            .unwrap();
+        *guard += 1;
+    }
+    assert_eq!(counts.inserts.load(Ordering::SeqCst), 1);
+    let existing = poirot_map.get_or_try_insert_with(1, || Err::<u32, ()>(()));
+    assert_eq!(*existing.unwrap(), 11);
+
+    poirot_map.close();
+    let closed = poirot_map.get_or_try_insert_with(2, || -> Result<u32, ()> {
+        panic!("closed maps do not build values")
+    });
+    assert_eq!(closed.err(), Some(GetOrInsertError::Closed));
+}
+
+#[test]
+fn hashmap_write_buffer() {
+    let counts = Arc::new(CountingObserver::default());
+    let poirot_map: Arc<ConcurrentHashMap<u32, u32>> = Arc::new(
+        ConcurrentHashMap::with_options(64, RandomState::new(), 4).with_observer(counts.clone()),
+    );
+    poirot_map.insert(0, 100);
+
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let poirot_map = poirot_map.clone();
+            thread::spawn(move || {
+                let mut buffer = poirot_map.write_buffer(|current, new| *current += new);
+                for x in 0..1000 {
+                    buffer.merge(x % 10, 1);
+                }
+                assert_eq!(buffer.len(), 10);
+                buffer.flush().unwrap();
+                assert!(buffer.is_empty());
+                buffer.insert(20, 1);
+                // Flushed on drop.
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    assert_eq!(*poirot_map.get(&0).unwrap(), 500);
+    for x in 1..10 {
+        assert_eq!(*poirot_map.get(&x).unwrap(), 400);
+    }
+    assert_eq!(*poirot_map.get(&20).unwrap(), 1);
+    assert_eq!(counts.inserts.load(Ordering::SeqCst), 11);
+
+    let mut buffer = poirot_map.write_buffer(|current, new| *current += new);
+    buffer.merge(30, 1);
+    buffer.insert(0, 0);
+    assert!(!poirot_map.contains(&30));
+    assert_eq!(*poirot_map.get(&0).unwrap(), 500);
+    buffer.flush().unwrap();
+    assert_eq!(*poirot_map.get(&30).unwrap(), 1);
+    assert_eq!(*poirot_map.get(&0).unwrap(), 0);
+}
+
+#[test]
+fn hashmap_unlocked_access() {
+    let counts = Arc::new(CountingObserver::default());
+    let mut poirot_map: ConcurrentHashMap<u32, u32> =
+        ConcurrentHashMap::with_options(64, RandomState::new(), 4).with_observer(counts.clone());
+    for x in 0..32 {
+        poirot_map.insert(x, x);
+    }
+
+    *poirot_map.get_mut_unlocked(&3).unwrap() += 100;
+    assert!(poirot_map.get_mut_unlocked(&99).is_none());
+    assert_eq!(*poirot_map.get(&3).unwrap(), 103);
+
+    for (k, v) in poirot_map.iter_mut_unlocked() {
+        *v = *k * 2;
+    }
+    assert!((0..32).all(|x| *poirot_map.get(&x).unwrap() == x * 2));
+    assert_eq!(poirot_map.iter_mut_unlocked().count(), 32);
+
+    poirot_map.clear_unlocked();
+    assert!(poirot_map.is_empty());
+    assert_eq!(counts.removes.load(Ordering::SeqCst), 32);
+}
+
+#[test]
+fn hashmap_entry_ref() {
+    let poirot_map: ConcurrentHashMap<String, u32> =
+        ConcurrentHashMap::with_options(16, RandomState::new(), 2);
+    for word in "a b a c a b".split(' ') {
+        *poirot_map.entry_ref(word).or_insert(0).unwrap() += 1;
+    }
+    assert_eq!(poirot_map.len(), 3);
+    assert_eq!(*poirot_map.get("a").unwrap(), 3);
+
+    let guard = poirot_map
+        .entry_ref("b")
+        .and_modify(|v| *v *= 10)
+        .or_default()
+        .unwrap();
+    assert_eq!(*guard, 20);
+    drop(guard);
+    assert_eq!(*poirot_map.entry_ref("d").or_default().unwrap(), 0);
+
+    match poirot_map.entry_ref("c") {
+        EntryRef::Occupied(entry) => assert_eq!(entry.remove(), Some(1)),
+        EntryRef::Vacant(_) => panic!("c should be present"),
+    }
+    match poirot_map.entry_ref("c") {
+        EntryRef::Occupied(_) => panic!("c should be gone"),
+        EntryRef::Vacant(entry) => assert_eq!(*entry.insert(7).unwrap(), 7),
+    }
+    assert_eq!(*poirot_map.get("c").unwrap(), 7);
+
+    poirot_map.close();
+    assert_eq!(
+        poirot_map.entry_ref("a").or_insert(0).err(),
+        Some(Error::Closed)
+    );
+    assert_eq!(
+        poirot_map
+            .entry_ref("e")
+            .or_insert_with(|| panic!("map is closed"))
+            .err(),
+        Some(Error::Closed)
+    );
+    match poirot_map.entry_ref("e") {
+        EntryRef::Occupied(_) => panic!("e should be absent"),
+        EntryRef::Vacant(entry) => {
+            let error = entry.insert(5).err().unwrap();
+            assert_eq!(error.error, Error::Closed);
+            assert_eq!(error.into_entry(), ("e".to_string(), 5));
+        }
+    }
+    assert!(poirot_map.is_empty());
+}
+
+#[test]
+fn hashmap_single_segment() {
+    let map = ConcurrentHashMap::with_options(16, RandomState::new(), 1);
+    assert_eq!(map.segment_count(), 1);
+    for x in 0..64 {
+        map.insert(x, x * 2);
+    }
+    assert_eq!(map.len(), 64);
+    assert!((0..64).all(|x| *map.get(&x).unwrap() == x * 2));
+    assert_eq!(map.remove(&7), Some(14));
+    assert!(!map.contains(&7));
+}
@@ -0,0 +1,64 @@
+#[macro_use]
+extern crate quickcheck;
+extern crate poirot;
+
+use poirot::ConcurrentIntMap;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::thread;
+
+quickcheck! {
+    // Small keys force repeated insert/remove on the same slots, so probe chains
+    // run over tombstones and the tables get rebuilt.
+    fn qc_int_map_matches_std(ops: Vec<(bool, u8, u32)>) -> bool {
+        let int_map = ConcurrentIntMap::with_options(0, 2);
+        let mut std_map = HashMap::new();
+        for &(insert, key, value) in &ops {
+            let key = u32::from(key);
+            let matches = if insert {
+                int_map.insert(key, value) == std_map.insert(key, value)
+            } else {
+                int_map.remove(key) == std_map.remove(&key)
+            };
+            if !matches {
+                return false;
+            }
+        }
+        int_map.len() == std_map.len()
+            && (0..256).all(|k| int_map.get(k).map(|v| *v) == std_map.get(&k).cloned())
+    }
+}
+
+#[test]
+fn int_map_single_segment() {
+    let int_map = ConcurrentIntMap::with_options(16, 1);
+    for x in 0..1000u64 {
+        int_map.insert(x << 32, x);
+    }
+    assert_eq!(int_map.len(), 1000);
+    *int_map.get_mut(5 << 32).unwrap() += 1;
+    assert_eq!(*int_map.get(5 << 32).unwrap(), 6);
+    assert!(!int_map.contains(5));
+    int_map.clear();
+    assert!(int_map.is_empty());
+}
+
+#[test]
+fn int_map_concurrent_updates() {
+    let int_map: Arc<ConcurrentIntMap<u64, u64>> = Arc::new(ConcurrentIntMap::new());
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let int_map = int_map.clone();
+            thread::spawn(move || {
+                for x in 0..10_000 {
+                    int_map.insert_or_update(x % 100, || 1, |v| *v += 1);
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(int_map.len(), 100);
+    assert!((0..100).all(|k| *int_map.get(k).unwrap() == 400));
+}
@@ -0,0 +1,149 @@
+extern crate poirot;
+extern crate rayon;
+
+use poirot::{Error, LoadingCache, MockClock};
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn loading_cache_loads_once_per_miss() {
+    let loads = AtomicUsize::new(0);
+    let cache = LoadingCache::new(|k: &u64| {
+        loads.fetch_add(1, Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(10));
+        k * 2
+    });
+
+    (0..256u64).into_par_iter().for_each(|x| {
+        assert_eq!(*cache.get(&(x % 4)).unwrap(), (x % 4) * 2);
+    });
+    assert_eq!(loads.load(Ordering::SeqCst), 4);
+
+    assert_eq!(cache.invalidate(&1), Some(2));
+    assert!(cache.get_if_present(&1).is_none());
+    assert_eq!(*cache.get_with(&1, |_| 7).unwrap(), 7);
+    assert_eq!(loads.load(Ordering::SeqCst), 4);
+}
+
+#[test]
+fn loading_cache_close() {
+    let loads = AtomicUsize::new(0);
+    let cache = LoadingCache::new(|k: &u64| {
+        loads.fetch_add(1, Ordering::SeqCst);
+        *k
+    });
+    assert_eq!(*cache.get(&1).unwrap(), 1);
+    let _pin = cache.map().pin(&1).unwrap();
+    assert_eq!(cache.close(), vec![]);
+
+    assert_eq!(*cache.get(&1).unwrap(), 1);
+    assert_eq!(cache.get(&2).err(), Some(Error::Closed));
+    assert_eq!(
+        cache.try_get_with(&2, |k| Some(*k)).err(),
+        Some(Error::Closed)
+    );
+    assert_eq!(loads.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn loading_cache_negative_ttl() {
+    let lookups = AtomicUsize::new(0);
+    let lookup = |k: &u64| {
+        lookups.fetch_add(1, Ordering::SeqCst);
+        if k.is_multiple_of(2) {
+            Some(*k)
+        } else {
+            None
+        }
+    };
+    let clock = MockClock::new();
+    let cache = LoadingCache::new(|k: &u64| *k)
+        .with_negative_ttl(Duration::from_millis(50))
+        .with_clock(clock.clone());
+
+    assert_eq!(cache.try_get_with(&2, lookup).unwrap().map(|v| *v), Some(2));
+    assert!(cache.try_get_with(&3, lookup).unwrap().is_none());
+    assert!(cache.try_get_with(&3, lookup).unwrap().is_none());
+    assert_eq!(lookups.load(Ordering::SeqCst), 2);
+
+    clock.advance(Duration::from_millis(60));
+    assert!(cache.try_get_with(&3, lookup).unwrap().is_none());
+    assert_eq!(lookups.load(Ordering::SeqCst), 3);
+    assert_eq!(*cache.get(&3).unwrap(), 3);
+
+    let stats = cache.stats();
+    assert_eq!(stats.hits, 1);
+    assert_eq!(stats.misses, 4);
+    assert_eq!(stats.load_successes, 2);
+    assert_eq!(stats.load_failures, 2);
+    assert_eq!(stats.expirations, 1);
+    let histogram_total: u64 = stats.load_time_histogram.iter().map(|&(_, n)| n).sum();
+    assert_eq!(histogram_total, 4);
+}
+
+#[test]
+fn loading_cache_mock_clock() {
+    let clock = MockClock::new();
+    let cache = LoadingCache::new(|k: &u64| *k)
+        .with_negative_ttl(Duration::from_secs(30))
+        .with_clock(clock.clone());
+
+    assert!(cache.try_get_with(&1, |_| None).unwrap().is_none());
+    clock.advance(Duration::from_secs(29));
+    assert!(cache.try_get_with(&1, |k| Some(*k)).unwrap().is_none());
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(
+        cache.try_get_with(&1, |k| Some(*k)).unwrap().map(|v| *v),
+        Some(1)
+    );
+    assert_eq!(cache.stats().expirations, 1);
+}
+
+#[test]
+fn loading_cache_expire_now() {
+    let clock = MockClock::new();
+    let cache = LoadingCache::new(|k: &u64| *k)
+        .with_negative_ttl(Duration::from_secs(30))
+        .with_clock(clock.clone());
+
+    for k in 0..10 {
+        assert!(cache.try_get_with(&k, |_| None).unwrap().is_none());
+    }
+    clock.advance(Duration::from_secs(10));
+    for k in 10..15 {
+        assert!(cache.try_get_with(&k, |_| None).unwrap().is_none());
+    }
+    assert_eq!(cache.expire_now(), 0);
+
+    clock.advance(Duration::from_secs(20));
+    assert_eq!(cache.expire_now(), 10);
+    assert_eq!(cache.expire_now(), 0);
+    assert_eq!(cache.stats().expirations, 10);
+    assert!(cache.try_get_with(&12, |k| Some(*k)).unwrap().is_none());
+}
+
+#[test]
+fn loading_cache_refresh_after_write() {
+    let clock = MockClock::new();
+    let version = AtomicUsize::new(0);
+    let cache = LoadingCache::new(|k: &u64| (*k, version.load(Ordering::SeqCst)))
+        .with_refresh_after_write(Duration::from_secs(60))
+        .with_clock(clock.clone());
+
+    assert_eq!(*cache.get(&1).unwrap(), (1, 0));
+    assert_eq!(*cache.get(&2).unwrap(), (2, 0));
+    version.store(1, Ordering::SeqCst);
+    clock.advance(Duration::from_secs(30));
+    assert_eq!(*cache.get(&1).unwrap(), (1, 0));
+    assert_eq!(cache.refresh_stale(), 0);
+
+    clock.advance(Duration::from_secs(30));
+    assert_eq!(*cache.get(&1).unwrap(), (1, 0));
+    assert_eq!(cache.refresh_stale(), 1);
+    assert_eq!(*cache.get(&1).unwrap(), (1, 1));
+    assert_eq!(*cache.get_if_present(&2).unwrap(), (2, 0));
+    assert_eq!(cache.refresh_stale(), 0);
+    assert_eq!(cache.stats().load_successes, 3);
+}
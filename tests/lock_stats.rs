@@ -0,0 +1,70 @@
+#![cfg(feature = "lock-stats")]
+
+extern crate poirot;
+
+use poirot::ConcurrentHashMap;
+use std::collections::hash_map::RandomState;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn lock_stats_track_write_guard_hold_time() {
+    let poirot_map = ConcurrentHashMap::with_options(16, RandomState::new(), 2);
+    poirot_map.insert(0, 0);
+    let segment = poirot_map.segment_index(&0);
+    let before = poirot_map.lock_stats()[segment].clone();
+    assert_eq!(before.acquisitions, 1);
+    assert_eq!(before.contended, 0);
+    {
+        let mut guard = poirot_map.get_mut(&0).unwrap();
+        *guard += 1;
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    let stats = poirot_map.lock_stats();
+    assert_eq!(stats.len(), poirot_map.segment_count());
+    let after = &stats[segment];
+    assert_eq!(after.acquisitions, 2);
+    assert!(after.total_hold_time >= Duration::from_millis(20));
+    let slow: u64 = after
+        .hold_time_histogram
+        .iter()
+        .filter(|&&(bound, _)| bound > Duration::from_millis(10))
+        .map(|&(_, count)| count)
+        .sum();
+    assert_eq!(slow, 1);
+    let total: u64 = after.hold_time_histogram.iter().map(|&(_, n)| n).sum();
+    assert_eq!(total, after.acquisitions);
+}
+
+#[test]
+fn lock_stats_count_contention() {
+    let poirot_map = ConcurrentHashMap::with_options(16, RandomState::new(), 2);
+    poirot_map.insert(0, 0);
+    let segment = poirot_map.segment_index(&0);
+    let guard = poirot_map.get_mut(&0).unwrap();
+    assert!(poirot_map.try_get_mut(&0).is_err());
+    assert_eq!(poirot_map.lock_stats()[segment].contended, 1);
+    drop(guard);
+}
+
+#[test]
+fn lock_stats_track_wait_time() {
+    let poirot_map = Arc::new(ConcurrentHashMap::with_options(16, RandomState::new(), 2));
+    poirot_map.insert(0, 0);
+    let segment = poirot_map.segment_index(&0);
+    let guard = poirot_map.get_mut(&0).unwrap();
+    let reader = {
+        let poirot_map = poirot_map.clone();
+        thread::spawn(move || *poirot_map.get(&0).unwrap())
+    };
+    thread::sleep(Duration::from_millis(20));
+    drop(guard);
+    assert_eq!(reader.join().unwrap(), 0);
+
+    let stats = &poirot_map.lock_stats()[segment];
+    assert_eq!(stats.contended_reads, 1);
+    assert_eq!(stats.contended, 0);
+    assert!(stats.total_wait_time >= Duration::from_millis(10));
+}
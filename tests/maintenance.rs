@@ -0,0 +1,38 @@
+extern crate poirot;
+
+use poirot::{LoadingCache, MaintenanceHandle, MockClock};
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn maintenance_thread_expires_negatives() {
+    let clock = MockClock::new();
+    let cache = Arc::new(
+        LoadingCache::new(|k: &u64| *k)
+            .with_negative_ttl(Duration::from_secs(30))
+            .with_clock(clock.clone()),
+    );
+    for k in 0..8 {
+        assert!(cache.try_get_with(&k, |_| None).unwrap().is_none());
+    }
+    clock.advance(Duration::from_secs(31));
+
+    let passes = Arc::new(AtomicUsize::new(0));
+    let counter = passes.clone();
+    let handle =
+        MaintenanceHandle::start_with(cache.clone(), Duration::from_millis(5), move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+    while cache.stats().expirations < 8 {
+        thread::sleep(Duration::from_millis(5));
+    }
+    handle.stop();
+
+    let after_stop = passes.load(Ordering::SeqCst);
+    assert!(after_stop >= 1);
+    thread::sleep(Duration::from_millis(20));
+    assert_eq!(passes.load(Ordering::SeqCst), after_stop);
+}
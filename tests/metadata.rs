@@ -0,0 +1,126 @@
+#![cfg(feature = "entry-metadata")]
+extern crate poirot;
+
+use poirot::{Clock, ConcurrentHashMap, MockClock};
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::time::Duration;
+
+#[test]
+fn entry_metadata() {
+    let clock = MockClock::new();
+    let start = clock.now();
+    let map: ConcurrentHashMap<u32, u32> =
+        ConcurrentHashMap::with_options(16, RandomState::new(), 2).with_clock(clock.clone());
+
+    map.insert(1, 10);
+    let meta = map.metadata(&1).unwrap();
+    assert_eq!(meta.created, start);
+    assert_eq!(meta.access_count, 0);
+
+    clock.advance(Duration::from_secs(5));
+    {
+        let guard = map.get(&1).unwrap();
+        assert_eq!(guard.metadata().access_count, 1);
+        assert_eq!(
+            guard.metadata().last_accessed,
+            start + Duration::from_secs(5)
+        );
+    }
+    *map.get_mut(&1).unwrap() += 1;
+    assert_eq!(map.metadata(&1).unwrap().access_count, 2);
+
+    // Overwriting keeps the original creation time.
+    clock.advance(Duration::from_secs(5));
+    map.insert(1, 11);
+    assert_eq!(map.metadata(&1).unwrap().created, start);
+
+    map.remove(&1);
+    assert!(map.metadata(&1).is_none());
+    map.insert_or_update(1, || 0, |v| *v += 1);
+    let meta = map.metadata(&1).unwrap();
+    assert_eq!(meta.created, start + Duration::from_secs(10));
+    assert_eq!(meta.access_count, 0);
+}
+
+#[test]
+fn age_ordering() {
+    let clock = MockClock::new();
+    let map: ConcurrentHashMap<u32, u32> =
+        ConcurrentHashMap::with_options(16, RandomState::new(), 2).with_clock(clock.clone());
+    for i in 0..5 {
+        map.insert(i, i * 10);
+        clock.advance(Duration::from_secs(1));
+    }
+    let by_age: Vec<u32> = map.iter_by_age().into_iter().map(|(k, _, _)| k).collect();
+    assert_eq!(by_age, vec![0, 1, 2, 3, 4]);
+
+    // Touching the oldest entries makes the newer ones coldest.
+    map.get(&0);
+    clock.advance(Duration::from_secs(1));
+    map.get(&1);
+    let coldest: Vec<(u32, u32)> = map.coldest(3).into_iter().map(|(k, v, _)| (k, v)).collect();
+    assert_eq!(coldest, vec![(2, 20), (3, 30), (4, 40)]);
+    assert_eq!(map.coldest(10).len(), 5);
+}
+
+#[test]
+fn inserted_guard_metadata() {
+    let clock = MockClock::new();
+    let start = clock.now();
+    let map: ConcurrentHashMap<u32, u32> =
+        ConcurrentHashMap::with_options(16, RandomState::new(), 2).with_clock(clock.clone());
+
+    let (_, guard) = map.insert_and_get(1, 1).unwrap();
+    assert_eq!(guard.metadata().created, start);
+    assert_eq!(guard.metadata().access_count, 0);
+}
+
+#[test]
+fn take_carries_metadata() {
+    let clock = MockClock::new();
+    let start = clock.now();
+    let map: ConcurrentHashMap<u32, u32> =
+        ConcurrentHashMap::with_options(16, RandomState::new(), 2).with_clock(clock.clone());
+    map.insert(1, 10);
+    map.insert(2, 20);
+    let _pin = map.pin(&2).unwrap();
+    clock.advance(Duration::from_secs(5));
+
+    let batch = map.take();
+    assert_eq!(batch.metadata(&1).unwrap().created, start);
+    assert!(map.metadata(&1).is_none());
+    assert_eq!(map.metadata(&2).unwrap().created, start);
+}
+
+#[derive(Default)]
+struct CollidingHasher;
+
+impl Hasher for CollidingHasher {
+    fn finish(&self) -> u64 {
+        0
+    }
+
+    fn write(&mut self, _: &[u8]) {}
+}
+
+#[test]
+fn colliding_keys_keep_their_own_metadata() {
+    let clock = MockClock::new();
+    let start = clock.now();
+    let map: ConcurrentHashMap<u32, u32, BuildHasherDefault<CollidingHasher>> =
+        ConcurrentHashMap::with_options(16, Default::default(), 2).with_clock(clock.clone());
+    map.insert(1, 10);
+    clock.advance(Duration::from_secs(5));
+    map.insert(2, 20);
+    map.get(&2);
+
+    assert_eq!(map.metadata(&1).unwrap().created, start);
+    assert_eq!(map.metadata(&1).unwrap().access_count, 0);
+    assert_eq!(map.metadata(&2).unwrap().access_count, 1);
+
+    map.remove(&2);
+    assert_eq!(map.metadata(&1).unwrap().created, start);
+    assert_eq!(map.iter_by_age().len(), 1);
+}
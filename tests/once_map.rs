@@ -0,0 +1,21 @@
+extern crate poirot;
+extern crate rayon;
+
+use poirot::OnceMap;
+use rayon::prelude::*;
+
+#[test]
+fn once_map_references_outlive_growth() {
+    let once_map = OnceMap::new();
+    let first: &String = once_map.insert(0, "zero".to_string());
+
+    (1..4096u64).into_par_iter().for_each(|x| {
+        once_map.get_or_insert_with(x, || x.to_string());
+    });
+
+    assert_eq!(first, "zero");
+    assert_eq!(once_map.insert(0, "other".to_string()), "zero");
+    assert!(std::ptr::eq(first, once_map.get(&0).unwrap()));
+    assert!((1..4096u64).all(|x| once_map.get(&x).unwrap() == &x.to_string()));
+    assert!(!once_map.contains(&4096));
+}
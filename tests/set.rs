@@ -1,6 +1,8 @@
 extern crate poirot;
 #[macro_use]
 extern crate quickcheck;
+#[cfg(feature = "rand")]
+extern crate rand;
 
 use poirot::ConcurrentHashSet;
 use std::collections::HashSet;
@@ -12,4 +14,43 @@ quickcheck! {
         xs.iter().cloned().for_each(|k| {poirot_set.insert(k); std_set.insert(k);});
         std_set.iter().all(|k| poirot_set.contains(k)) && poirot_set.into_iter().all(|k| std_set.contains(&k))
     }
+
+    fn qc_poirot_set_symmetric_difference(xs: Vec<u8>, ys: Vec<u8>) -> bool {
+        let (a, b) = (ConcurrentHashSet::new(), ConcurrentHashSet::new());
+        xs.iter().for_each(|&x| {a.insert(x);});
+        ys.iter().for_each(|&y| {b.insert(y);});
+        let expected: HashSet<u8> = xs.iter().cloned().collect::<HashSet<_>>()
+            .symmetric_difference(&ys.iter().cloned().collect())
+            .cloned()
+            .collect();
+        a.symmetric_difference_with(&b).into_iter().collect::<HashSet<_>>() == expected
+            && a.symmetric_difference_with(&a).into_iter().next().is_none()
+    }
+}
+
+#[test]
+fn set_to_vec() {
+    let poirot_set = ConcurrentHashSet::new();
+    for x in 0..100u32 {
+        poirot_set.insert(x);
+    }
+    let mut elements = poirot_set.to_vec();
+    elements.sort();
+    assert_eq!(elements, (0..100).collect::<Vec<_>>());
+    assert_eq!(poirot_set.to_vec().len(), 100);
+}
+
+#[cfg(feature = "rand")]
+#[test]
+fn set_sample() {
+    let poirot_set = ConcurrentHashSet::new();
+    for x in 0..100u32 {
+        poirot_set.insert(x);
+    }
+    let mut rng = rand::thread_rng();
+    let sample = poirot_set.sample(10, &mut rng);
+    assert_eq!(sample.len(), 10);
+    assert_eq!(sample.iter().collect::<HashSet<_>>().len(), 10);
+    assert!(sample.iter().all(|x| poirot_set.contains(x)));
+    assert_eq!(poirot_set.sample(1000, &mut rng).len(), 100);
 }
@@ -0,0 +1,35 @@
+#![cfg(feature = "proptest")]
+
+#[macro_use]
+extern crate proptest;
+extern crate poirot;
+
+use poirot::{map_strategy, set_strategy, ConcurrentHashMap, ConcurrentHashSet};
+use proptest::prelude::any;
+use std::collections::hash_map::RandomState;
+
+proptest! {
+    #[test]
+    fn prop_arbitrary_map(map in any::<ConcurrentHashMap<u8, u32>>()) {
+        prop_assert!((1..=32).contains(&map.segment_count()));
+        prop_assert_eq!(map.iter().count(), map.len());
+    }
+
+    #[test]
+    fn prop_map_strategy(map in map_strategy::<_, _, RandomState, _, _, _>(0..10u8, 100..200u32, 5..20)) {
+        prop_assert!(!map.is_empty() && map.len() <= 10);
+        prop_assert!(map.iter().all(|(k, v)| k < 10 && (100..200).contains(&v)));
+    }
+
+    #[test]
+    fn prop_arbitrary_set(set in any::<ConcurrentHashSet<u16>>()) {
+        let copy = set.clone();
+        prop_assert!(set.to_vec().iter().all(|k| copy.contains(k)));
+    }
+
+    #[test]
+    fn prop_set_strategy(set in set_strategy::<_, RandomState, _, _>(0..1000u16, 1..50)) {
+        prop_assert!(!set.to_vec().is_empty());
+        prop_assert!(set.to_vec().iter().all(|&k| k < 1000));
+    }
+}
@@ -0,0 +1,41 @@
+#[macro_use]
+extern crate quickcheck;
+extern crate poirot;
+
+use poirot::{ConcurrentStringMap, SmallString};
+use std::collections::HashSet;
+
+quickcheck! {
+    fn qc_small_string_round_trips(s: String) -> bool {
+        let small = SmallString::from(s.as_str());
+        let set: HashSet<SmallString> = Some(small.clone()).into_iter().collect();
+        small.as_str() == s
+            && small.is_inline() == (s.len() <= 22)
+            && SmallString::from(s.clone()) == small
+            && set.contains(s.as_str())
+    }
+}
+
+#[test]
+fn string_map_short_and_long_keys() {
+    let string_map = ConcurrentStringMap::new();
+    let short = "x".repeat(22);
+    let long = "x".repeat(23);
+    assert!(SmallString::from(short.as_str()).is_inline());
+    assert!(!SmallString::from(long.as_str()).is_inline());
+
+    string_map.insert(short.as_str(), 1);
+    string_map.insert(long.clone(), 2);
+    assert_eq!(string_map.len(), 2);
+    assert_eq!(*string_map.get(&short).unwrap(), 1);
+    *string_map.get_mut(&long).unwrap() += 1;
+    assert_eq!(*string_map.get(&long).unwrap(), 3);
+
+    string_map.insert_or_update("hits", || 1, |v| *v += 1);
+    string_map.insert_or_update("hits", || 1, |v| *v += 1);
+    assert_eq!(*string_map.get("hits").unwrap(), 2);
+
+    assert_eq!(string_map.remove(&short), Some(1));
+    assert!(!string_map.contains(&short));
+    assert!(string_map.contains("hits"));
+}
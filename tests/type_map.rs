@@ -0,0 +1,46 @@
+extern crate poirot;
+
+use poirot::ConcurrentTypeMap;
+use std::sync::Arc;
+use std::thread;
+
+#[derive(Debug, PartialEq)]
+struct RequestId(u64);
+
+#[test]
+fn type_map_stores_one_value_per_type() {
+    let type_map = ConcurrentTypeMap::new();
+    assert_eq!(type_map.insert(RequestId(1)), None);
+    assert_eq!(type_map.insert("name".to_string()), None);
+    assert_eq!(type_map.insert(RequestId(2)), Some(RequestId(1)));
+    assert_eq!(type_map.len(), 2);
+
+    assert_eq!(*type_map.get::<RequestId>().unwrap(), RequestId(2));
+    type_map.get_mut::<String>().unwrap().push('d');
+    assert_eq!(&*type_map.get::<String>().unwrap(), "named");
+    assert!(type_map.get::<u32>().is_none());
+
+    assert_eq!(type_map.remove::<String>(), Some("named".to_string()));
+    assert!(!type_map.contains::<String>());
+    assert!(type_map.contains::<RequestId>());
+}
+
+#[test]
+fn type_map_get_or_insert_with() {
+    let type_map = Arc::new(ConcurrentTypeMap::new());
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let type_map = type_map.clone();
+            thread::spawn(move || {
+                for _ in 0..100 {
+                    *type_map.get_or_insert_with(|| 0u64) += 1;
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(*type_map.get::<u64>().unwrap(), 400);
+    assert_eq!(type_map.len(), 1);
+}